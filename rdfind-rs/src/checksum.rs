@@ -4,76 +4,374 @@
 // This file implements the Checksum functionality in Rust.
 // Ported on 2025-05-05.
 
-use std::io;
 use sha1::Digest as Sha1Digest;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, LazyLock, RwLock};
+
+/// A hash function to tell files apart with.
+///
+/// Implement this to use another algorithm, for instance a hardware
+/// accelerated or keyed one, and make it available by name with
+/// `ChecksumType::register`. The built in checksums are implemented on top
+/// of it as well.
+pub trait DuplicateHasher: Send {
+    /// Feeds the next part of the file to the hasher.
+    fn update(&mut self, data: &[u8]);
+
+    /// The digest of everything fed so far, `digest_length` bytes long.
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+
+    /// A copy of the hasher in its current state. Used to get the digest of
+    /// the first part of a file while going on with the rest.
+    fn clone_box(&self) -> Box<dyn DuplicateHasher>;
+
+    /// The length of the digest in bytes.
+    fn digest_length(&self) -> usize;
+}
+
+type HasherFactory = Arc<dyn Fn() -> Box<dyn DuplicateHasher> + Send + Sync>;
+
+struct Registration {
+    name: &'static str,
+    factory: HasherFactory,
+}
+
+// the hashers which can be chosen by name. a ChecksumType is an index into
+// it, and the built in ones come first in the order of their constants.
+static REGISTRY: LazyLock<RwLock<Vec<Registration>>> = LazyLock::new(|| {
+    fn builtin<H: DuplicateHasher + 'static>(
+        name: &'static str,
+        factory: fn() -> H,
+    ) -> Registration {
+        Registration {
+            name,
+            factory: Arc::new(move || Box::new(factory())),
+        }
+    }
+    RwLock::new(vec![
+        builtin("md5", md5::Context::new),
+        builtin("sha1", sha1::Sha1::new),
+        builtin("sha256", sha2::Sha256::new),
+        builtin("sha512", sha2::Sha512::new),
+        builtin("xxh128", xxhash_rust::xxh3::Xxh3::new),
+        builtin("blake3", blake3::Hasher::new),
+        builtin("crc32c", || Crc32c(0)),
+    ])
+});
+
+/// Identifies a registered hasher.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChecksumType(usize);
+
+impl ChecksumType {
+    pub const MD5: ChecksumType = ChecksumType(0);
+    pub const SHA1: ChecksumType = ChecksumType(1);
+    pub const SHA256: ChecksumType = ChecksumType(2);
+    pub const SHA512: ChecksumType = ChecksumType(3);
+    pub const XXH128: ChecksumType = ChecksumType(4);
+    pub const BLAKE3: ChecksumType = ChecksumType(5);
+    pub const CRC32C: ChecksumType = ChecksumType(6);
+
+    /// The name used for this checksum on the command line.
+    pub fn name(&self) -> &'static str {
+        REGISTRY.read().unwrap()[self.0].name
+    }
+
+    /// The inverse of `name`. Names joined with + give their combination,
+    /// see `combine`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let found = REGISTRY
+            .read()
+            .unwrap()
+            .iter()
+            .position(|r| r.name == name)
+            .map(ChecksumType);
+        if found.is_some() || !name.contains('+') {
+            return found;
+        }
+        let parts = name
+            .split('+')
+            .map(Self::from_name)
+            .collect::<Option<Vec<_>>>()?;
+        Self::combine(&parts).ok()
+    }
+
+    /// A checksum computing all of `parts` in one pass, named by joining
+    /// their names with +. Its digest is theirs after each other, so two
+    /// files have the same digest only if all of the checksums match.
+    pub fn combine(parts: &[ChecksumType]) -> Result<Self, RegisterError> {
+        if let [single] = parts {
+            return Ok(*single);
+        }
+        let name = parts.iter().map(|t| t.name()).collect::<Vec<_>>().join("+");
+        let parts = parts.to_vec();
+        let factory = move || -> Box<dyn DuplicateHasher> {
+            Box::new(Combined(
+                parts.iter().map(|&t| Checksum::new(t).hasher).collect(),
+            ))
+        };
+        match Self::add(&name, Arc::new(factory)) {
+            Err(RegisterError::NameTaken { .. }) => {
+                Ok(Self::from_name(&name).expect("the name was just taken"))
+            }
+            ret => ret,
+        }
+    }
+
+    /// All checksums which can be chosen, the built in ones first.
+    pub fn registered() -> Vec<Self> {
+        (0..REGISTRY.read().unwrap().len())
+            .map(ChecksumType)
+            .collect()
+    }
 
+    /// Makes the hasher made by `factory` available as `name`, which may
+    /// consist of ASCII letters, digits, - and _.
+    pub fn register<F>(name: &str, factory: F) -> Result<Self, RegisterError>
+    where
+        F: Fn() -> Box<dyn DuplicateHasher> + Send + Sync + 'static,
+    {
+        let valid_name = !name.is_empty()
+            && name
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_');
+        if !valid_name {
+            return Err(RegisterError::InvalidName {
+                name: name.to_string(),
+            });
+        }
+        Self::add(name, Arc::new(factory))
+    }
 
-// Enum for supported checksum types
-pub enum ChecksumType {
-    SHA1,
-    SHA256,
-    SHA512,
-    MD5,
-    XXH128,
+    fn add(name: &str, factory: HasherFactory) -> Result<Self, RegisterError> {
+        if factory().digest_length() == 0 {
+            return Err(RegisterError::EmptyDigest {
+                name: name.to_string(),
+            });
+        }
+        let mut registry = REGISTRY.write().unwrap();
+        if registry.iter().any(|r| r.name == name) {
+            return Err(RegisterError::NameTaken {
+                name: name.to_string(),
+            });
+        }
+        registry.push(Registration {
+            // registrations live as long as the program, like the built in
+            // names do
+            name: Box::leak(name.to_string().into_boxed_str()),
+            factory,
+        });
+        Ok(ChecksumType(registry.len() - 1))
+    }
 }
 
-// State for each checksum type
-pub enum ChecksumState {
-    SHA1(sha1::Sha1),
-    SHA256(sha2::Sha256),
-    SHA512(sha2::Sha512),
-    MD5(md5::Context),
-    XXH128(xxhash_rust::xxh3::Xxh3),
+impl fmt::Debug for ChecksumType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Why `ChecksumType::register` refused a hasher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegisterError {
+    /// The name is empty or has characters other than letters, digits, -
+    /// and _.
+    InvalidName { name: String },
+    /// Another hasher is registered with the name.
+    NameTaken { name: String },
+    /// The hasher gives an empty digest.
+    EmptyDigest { name: String },
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::InvalidName { name } => {
+                write!(f, "invalid checksum name \"{}\"", name)
+            }
+            RegisterError::NameTaken { name } => {
+                write!(f, "a checksum named \"{}\" is already registered", name)
+            }
+            RegisterError::EmptyDigest { name } => {
+                write!(f, "checksum \"{}\" has an empty digest", name)
+            }
+        }
+    }
 }
 
+impl std::error::Error for RegisterError {}
+
 pub struct Checksum {
     checksum_type: ChecksumType,
-    state: ChecksumState,
+    hasher: Box<dyn DuplicateHasher>,
 }
 
 impl Checksum {
     pub fn new(checksum_type: ChecksumType) -> Self {
-        let state = match &checksum_type {
-            ChecksumType::SHA1 => ChecksumState::SHA1(sha1::Sha1::new()),
-            ChecksumType::SHA256 => ChecksumState::SHA256(sha2::Sha256::new()),
-            ChecksumType::SHA512 => ChecksumState::SHA512(sha2::Sha512::new()),
-            ChecksumType::MD5 => ChecksumState::MD5(md5::Context::new()),
-            ChecksumType::XXH128 => ChecksumState::XXH128(xxhash_rust::xxh3::Xxh3::new()),
-        };
+        // the lock is not held while making the hasher, a combined one
+        // makes its parts with Checksum::new
+        let factory = REGISTRY.read().unwrap()[checksum_type.0].factory.clone();
+        let hasher = factory();
         Self {
             checksum_type,
-            state,
+            hasher,
         }
     }
 
+    pub fn checksum_type(&self) -> ChecksumType {
+        self.checksum_type
+    }
+
     pub fn update(&mut self, buffer: &[u8]) -> io::Result<()> {
-        match &mut self.state {
-            ChecksumState::SHA1(hasher) => hasher.update(buffer),
-            ChecksumState::SHA256(hasher) => hasher.update(buffer),
-            ChecksumState::SHA512(hasher) => hasher.update(buffer),
-            ChecksumState::MD5(hasher) => hasher.consume(buffer),
-            ChecksumState::XXH128(hasher) => hasher.update(buffer),
-        }
+        self.hasher.update(buffer);
         Ok(())
     }
 
     pub fn get_digest_length(&self) -> usize {
-        match self.checksum_type {
-            ChecksumType::SHA1 => 20,
-            ChecksumType::SHA256 => 32,
-            ChecksumType::SHA512 => 64,
-            ChecksumType::MD5 => 16,
-            ChecksumType::XXH128 => 16,
-        }
+        self.hasher.digest_length()
     }
 
     pub fn finalize_to_vec(self) -> Vec<u8> {
-        match self.state {
-            ChecksumState::SHA1(hasher) => hasher.finalize().to_vec(),
-            ChecksumState::SHA256(hasher) => hasher.finalize().to_vec(),
-            ChecksumState::SHA512(hasher) => hasher.finalize().to_vec(),
-            ChecksumState::MD5(hasher) => hasher.compute().to_vec(),
-            ChecksumState::XXH128(hasher) => hasher.digest128().to_le_bytes().to_vec(),
+        self.hasher.finalize()
+    }
+}
+
+/// Clone it to get the checksum of what was seen so far while going on
+/// with the rest.
+impl Clone for Checksum {
+    fn clone(&self) -> Self {
+        Checksum {
+            checksum_type: self.checksum_type,
+            hasher: self.hasher.clone_box(),
         }
     }
 }
+
+// the hashers from the RustCrypto crates
+macro_rules! digest_hasher {
+    ($hasher:ty, $length:expr) => {
+        impl DuplicateHasher for $hasher {
+            fn update(&mut self, data: &[u8]) {
+                Sha1Digest::update(self, data);
+            }
+
+            fn finalize(self: Box<Self>) -> Vec<u8> {
+                Sha1Digest::finalize(*self).to_vec()
+            }
+
+            fn clone_box(&self) -> Box<dyn DuplicateHasher> {
+                Box::new(self.clone())
+            }
+
+            fn digest_length(&self) -> usize {
+                $length
+            }
+        }
+    };
+}
+
+digest_hasher!(sha1::Sha1, 20);
+digest_hasher!(sha2::Sha256, 32);
+digest_hasher!(sha2::Sha512, 64);
+
+impl DuplicateHasher for md5::Context {
+    fn update(&mut self, data: &[u8]) {
+        self.consume(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.compute().to_vec()
+    }
+
+    fn clone_box(&self) -> Box<dyn DuplicateHasher> {
+        Box::new(self.clone())
+    }
+
+    fn digest_length(&self) -> usize {
+        16
+    }
+}
+
+impl DuplicateHasher for xxhash_rust::xxh3::Xxh3 {
+    fn update(&mut self, data: &[u8]) {
+        xxhash_rust::xxh3::Xxh3::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.digest128().to_le_bytes().to_vec()
+    }
+
+    fn clone_box(&self) -> Box<dyn DuplicateHasher> {
+        Box::new(self.clone())
+    }
+
+    fn digest_length(&self) -> usize {
+        16
+    }
+}
+
+impl DuplicateHasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        blake3::Hasher::finalize(&self).as_bytes().to_vec()
+    }
+
+    fn clone_box(&self) -> Box<dyn DuplicateHasher> {
+        Box::new(self.clone())
+    }
+
+    fn digest_length(&self) -> usize {
+        blake3::OUT_LEN
+    }
+}
+
+#[derive(Clone)]
+struct Crc32c(u32);
+
+impl DuplicateHasher for Crc32c {
+    fn update(&mut self, data: &[u8]) {
+        self.0 = crc32c::crc32c_append(self.0, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.to_le_bytes().to_vec()
+    }
+
+    fn clone_box(&self) -> Box<dyn DuplicateHasher> {
+        Box::new(self.clone())
+    }
+
+    fn digest_length(&self) -> usize {
+        4
+    }
+}
+
+// several hashers fed the same data, see ChecksumType::combine
+struct Combined(Vec<Box<dyn DuplicateHasher>>);
+
+impl DuplicateHasher for Combined {
+    fn update(&mut self, data: &[u8]) {
+        for hasher in &mut self.0 {
+            hasher.update(data);
+        }
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0
+            .into_iter()
+            .flat_map(|hasher| hasher.finalize())
+            .collect()
+    }
+
+    fn clone_box(&self) -> Box<dyn DuplicateHasher> {
+        Box::new(Combined(self.0.iter().map(|h| h.clone_box()).collect()))
+    }
+
+    fn digest_length(&self) -> usize {
+        self.0.iter().map(|hasher| hasher.digest_length()).sum()
+    }
+}
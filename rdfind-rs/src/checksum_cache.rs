@@ -0,0 +1,281 @@
+// See LICENSE for further details.
+
+use crate::checksum::{Checksum, ChecksumType};
+use crate::easy_random::EasyRandom;
+use crate::fileinfo::FileInfo;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// the first line of a cache, which is checked when it is read
+const HEADER: &str = "# rdfind checksum cache 1";
+
+/// Entries not used for this many days are dropped when the cache is saved.
+pub const MAX_AGE_DAYS: i64 = 30;
+
+// what is known about a file the last time it was checksummed
+struct Entry {
+    size: i64,
+    mtime: i64,
+    mtime_nsec: i64,
+    digest: Vec<u8>,
+    // the day, counted from the epoch, the entry was last used
+    seen: i64,
+}
+
+// a line which was not understood, like one for a checksum which is not
+// registered in this run. it is written back as it was, until its last use,
+// if it tells, is too long ago.
+struct Unknown {
+    line: String,
+    seen: Option<i64>,
+}
+
+/// Checksums from earlier runs, so unchanged files need not be read again.
+///
+/// A checksum is used if the file has the same device, inode, size and
+/// modification time as when it was computed. A file changing any of them
+/// replaces its old entry. Entries which were not used for `MAX_AGE_DAYS`
+/// days, like those of deleted files, are dropped. The cache is shared
+/// between the threads reading files, and written back with `save`.
+pub struct ChecksumCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<(u64, u64, ChecksumType), Entry>>,
+    unknown: Vec<Unknown>,
+    today: i64,
+    // whether anything changed since the cache was read
+    dirty: AtomicBool,
+}
+
+impl ChecksumCache {
+    /// Where the cache is kept by default, $XDG_CACHE_HOME/rdfind/checksums
+    /// or ~/.cache/rdfind/checksums.
+    pub fn default_path() -> Option<PathBuf> {
+        let cachehome = match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+        };
+        Some(cachehome.join("rdfind").join("checksums"))
+    }
+
+    /// Reads the cache at `path`. A cache which does not exist yet is empty.
+    /// Fails with InvalidData if the file is not a cache of this version.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut entries = HashMap::new();
+        let mut unknown = Vec::new();
+        // the checksum and digest length of each name, looked up once
+        let mut types = HashMap::new();
+        match File::open(&path) {
+            Ok(f) => {
+                let mut lines = BufReader::new(f).lines();
+                if lines
+                    .next()
+                    .transpose()?
+                    .is_some_and(|header| header != HEADER)
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "not a checksum cache of this version of rdfind",
+                    ));
+                }
+                for line in lines {
+                    let line = line?;
+                    if line.starts_with('#') {
+                        continue;
+                    }
+                    match parse_line(&line, &mut types) {
+                        Some((key, entry)) => {
+                            entries.insert(key, entry);
+                        }
+                        None => unknown.push(Unknown {
+                            seen: line.split(' ').nth(7).and_then(|seen| seen.parse().ok()),
+                            line,
+                        }),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(ChecksumCache {
+            path,
+            entries: Mutex::new(entries),
+            unknown,
+            today: today(),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Removes the cache at `path`, if there is one.
+    pub fn clear(path: &Path) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The checksum of `file`, if it is known and the file is unchanged.
+    pub fn get(&self, file: &FileInfo, checksumtype: ChecksumType) -> Option<Vec<u8>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(&(file.stat_dev, file.stat_ino, checksumtype))?;
+        let unchanged = (entry.size, entry.mtime, entry.mtime_nsec)
+            == (file.stat_size, file.stat_mtime, file.stat_mtime_nsec);
+        if unchanged && entry.seen != self.today {
+            entry.seen = self.today;
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        unchanged.then(|| entry.digest.clone())
+    }
+
+    /// Remembers the checksum of `file`, replacing what was known about it.
+    pub fn insert(&self, file: &FileInfo, checksumtype: ChecksumType, digest: &[u8]) {
+        let entry = Entry {
+            size: file.stat_size,
+            mtime: file.stat_mtime,
+            mtime_nsec: file.stat_mtime_nsec,
+            digest: digest.to_vec(),
+            seen: self.today,
+        };
+        self.dirty.store(true, Ordering::Relaxed);
+        self.entries
+            .lock()
+            .unwrap()
+            .insert((file.stat_dev, file.stat_ino, checksumtype), entry);
+    }
+
+    /// Writes the cache back to its path, creating the directory if
+    /// needed, leaving out entries which are too old. A temporary is
+    /// written first, so an interrupted save leaves the old cache in place.
+    /// Nothing is written if nothing changed.
+    pub fn save(&self) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|_, entry| !self.expired(entry.seen));
+        let pruned = entries.len() != before
+            || self
+                .unknown
+                .iter()
+                .any(|unknown| unknown.seen.is_some_and(|seen| self.expired(seen)));
+        drop(entries);
+        if !pruned && !self.dirty.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tempname = format!(
+            ".checksums.{}",
+            EasyRandom::new().make_random_file_string(12)
+        );
+        let temppath = self.path.with_file_name(tempname);
+        let ret = self.write_to(&temppath);
+        if ret.is_err() {
+            let _ = fs::remove_file(&temppath);
+            return ret;
+        }
+        fs::rename(&temppath, &self.path)
+    }
+
+    // whether an entry last used on day seen is too old to keep.
+    fn expired(&self, seen: i64) -> bool {
+        self.today - seen > MAX_AGE_DAYS
+    }
+
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(path)?);
+        writeln!(f, "{}", HEADER)?;
+        writeln!(
+            f,
+            "# checksum device inode size mtime mtime_nsec digest seen"
+        )?;
+        let entries = self.entries.lock().unwrap();
+        for ((dev, ino, checksumtype), entry) in entries.iter() {
+            write!(
+                f,
+                "{} {} {} {} {} {} ",
+                checksumtype.name(),
+                dev,
+                ino,
+                entry.size,
+                entry.mtime,
+                entry.mtime_nsec
+            )?;
+            for byte in &entry.digest {
+                write!(f, "{:02x}", byte)?;
+            }
+            writeln!(f, " {}", entry.seen)?;
+        }
+        for unknown in &self.unknown {
+            if unknown.seen.is_none_or(|seen| !self.expired(seen)) {
+                writeln!(f, "{}", unknown.line)?;
+            }
+        }
+        f.flush()
+    }
+}
+
+// the number of days since the epoch.
+fn today() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| (d.as_secs() / 86400) as i64)
+}
+
+// parses "checksum device inode size mtime mtime_nsec digest seen" as
+// written by write_to. types keeps the checksum and digest length of the
+// names seen so far, or None for names which are not registered.
+fn parse_line(
+    line: &str,
+    types: &mut HashMap<String, Option<(ChecksumType, usize)>>,
+) -> Option<((u64, u64, ChecksumType), Entry)> {
+    let mut fields = line.split(' ');
+    let name = fields.next()?;
+    let (checksumtype, digest_length) = match types.get(name) {
+        Some(&found) => found,
+        None => {
+            // not from_name, which would register the combination of names
+            // joined with +
+            let found = ChecksumType::registered()
+                .into_iter()
+                .find(|t| t.name() == name)
+                .map(|t| (t, Checksum::new(t).get_digest_length()));
+            types.insert(name.to_string(), found);
+            found
+        }
+    }?;
+    let dev = fields.next()?.parse().ok()?;
+    let ino = fields.next()?.parse().ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let mtime = fields.next()?.parse().ok()?;
+    let mtime_nsec = fields.next()?.parse().ok()?;
+    let hex = fields.next()?.as_bytes();
+    let seen = fields.next()?.parse().ok()?;
+    if fields.next().is_some() || hex.len() % 2 != 0 {
+        return None;
+    }
+    let digest = hex
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    if digest.len() != digest_length {
+        return None;
+    }
+    let entry = Entry {
+        size,
+        mtime,
+        mtime_nsec,
+        digest,
+        seen,
+    };
+    Some(((dev, ino, checksumtype), entry))
+}
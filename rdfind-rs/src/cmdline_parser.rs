@@ -2,86 +2,187 @@
 // Ported on 2025-05-05.
 
 use std::env;
-use std::process;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+
+/// What went wrong while parsing the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The parser was asked to look past the last argument, which is a
+    /// programming error in the caller.
+    OutOfBounds { index: usize, argc: usize },
+    /// A boolean option was the last argument.
+    MissingBool { arg: String },
+    /// A boolean option was followed by something else than true or false.
+    InvalidBool { arg: String, value: OsString },
+    /// A string option was the last argument.
+    MissingString { arg: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::OutOfBounds { index, argc } => {
+                write!(f, "out of bounds: index={} argc={}", index, argc)
+            }
+            ParseError::MissingBool { arg } => write!(
+                f,
+                "expected true or false after {}, not end of argument list.",
+                arg
+            ),
+            ParseError::InvalidBool { arg, value } => write!(
+                f,
+                "expected true or false after {}, not \"{}\"",
+                arg,
+                value.to_string_lossy()
+            ),
+            ParseError::MissingString { arg } => {
+                write!(
+                    f,
+                    "expected string after {}, not end of argument list.",
+                    arg
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 pub struct Parser {
-    args: Vec<String>,
+    args: Vec<OsString>,
     index: usize,
     last_bool_result: bool,
-    last_str_result: String,
+    last_str_result: OsString,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Parser {
+    /// Parses the arguments the program was started with.
     pub fn new() -> Self {
-        let args: Vec<String> = env::args().collect();
+        Self::from_args(env::args_os())
+    }
+
+    /// Parses `args`, where the first element is the program name.
+    pub fn from_args<I, T>(args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
         Parser {
-            args,
+            args: args.into_iter().map(Into::into).collect(),
             index: 1, // skip program name
             last_bool_result: false,
-            last_str_result: String::new(),
+            last_str_result: OsString::new(),
         }
     }
 
-    pub fn try_parse_bool(&mut self, arg: &str) -> bool {
+    fn check_bounds(&self) -> Result<(), ParseError> {
         if self.index >= self.args.len() {
-            eprintln!(
-                "out of bounds: index={} argc={}",
-                self.index,
-                self.args.len()
-            );
-            process::exit(1);
+            return Err(ParseError::OutOfBounds {
+                index: self.index,
+                argc: self.args.len(),
+            });
         }
-        if arg == self.args[self.index] {
-            if self.index + 1 >= self.args.len() {
-                eprintln!(
-                    "expected true or false after {} not end of argument list.",
-                    arg
-                );
-                process::exit(1);
-            }
-            let value = &self.args[self.index + 1];
-            if value == "true" {
-                self.last_bool_result = true;
-                self.index += 1;
-                return true;
-            }
-            if value == "false" {
-                self.last_bool_result = false;
-                self.index += 1;
-                return true;
-            }
-            eprintln!("expected true or false after {} not '{}'.", arg, value);
-            process::exit(1);
+        Ok(())
+    }
+
+    /// If the current argument is `arg`, consumes it together with the
+    /// following true or false. Returns whether `arg` matched.
+    pub fn try_parse_bool(&mut self, arg: &str) -> Result<bool, ParseError> {
+        self.check_bounds()?;
+        if self.args[self.index] != arg {
+            // no match. keep searching.
+            return Ok(false);
         }
-        false
+        let Some(value) = self.args.get(self.index + 1) else {
+            return Err(ParseError::MissingBool {
+                arg: arg.to_string(),
+            });
+        };
+        self.last_bool_result = if value == "true" {
+            true
+        } else if value == "false" {
+            false
+        } else {
+            return Err(ParseError::InvalidBool {
+                arg: arg.to_string(),
+                value: value.clone(),
+            });
+        };
+        self.index += 1;
+        Ok(true)
     }
 
-    pub fn try_parse_string(&mut self, arg: &str) -> bool {
-        if self.index >= self.args.len() {
-            eprintln!(
-                "out of bounds: index={} argc={}",
-                self.index,
-                self.args.len()
-            );
-            process::exit(1);
+    /// If the current argument is `arg`, consumes it together with the
+    /// following value. Returns whether `arg` matched.
+    pub fn try_parse_string(&mut self, arg: &str) -> Result<bool, ParseError> {
+        self.check_bounds()?;
+        if self.args[self.index] != arg {
+            // no match. keep searching.
+            return Ok(false);
+        }
+        let Some(value) = self.args.get(self.index + 1) else {
+            return Err(ParseError::MissingString {
+                arg: arg.to_string(),
+            });
+        };
+        self.last_str_result = value.clone();
+        self.index += 1;
+        Ok(true)
+    }
+
+    /// If the current argument is the GNU style long option `arg`, consumes
+    /// it. The option is true unless given as `arg=false`. Returns whether
+    /// `arg` matched.
+    pub fn try_parse_flag(&mut self, arg: &str) -> Result<bool, ParseError> {
+        self.check_bounds()?;
+        let current = self.args[self.index].as_bytes();
+        if current == arg.as_bytes() {
+            self.last_bool_result = true;
+            return Ok(true);
         }
-        if arg == self.args[self.index] {
-            if self.index + 1 >= self.args.len() {
-                eprintln!("expected string after {} not end of argument list.", arg);
-                process::exit(1);
+        let Some(value) = long_option_value(current, arg) else {
+            // no match. keep searching.
+            return Ok(false);
+        };
+        self.last_bool_result = match value {
+            b"true" => true,
+            b"false" => false,
+            _ => {
+                return Err(ParseError::InvalidBool {
+                    arg: arg.to_string(),
+                    value: OsStr::from_bytes(value).to_os_string(),
+                });
             }
-            self.last_str_result = self.args[self.index + 1].clone();
-            self.index += 1;
-            return true;
+        };
+        Ok(true)
+    }
+
+    /// If the current argument is the GNU style long option `arg`, consumes
+    /// it together with its value, given either as `arg=value` or as the
+    /// next argument. Returns whether `arg` matched.
+    pub fn try_parse_value(&mut self, arg: &str) -> Result<bool, ParseError> {
+        self.check_bounds()?;
+        let current = self.args[self.index].as_bytes();
+        if let Some(value) = long_option_value(current, arg) {
+            self.last_str_result = OsStr::from_bytes(value).to_os_string();
+            return Ok(true);
         }
-        false
+        self.try_parse_string(arg)
     }
 
     pub fn get_parsed_bool(&self) -> bool {
         self.last_bool_result
     }
 
-    pub fn get_parsed_string(&self) -> &str {
+    pub fn get_parsed_string(&self) -> &OsStr {
         &self.last_str_result
     }
 
@@ -102,19 +203,19 @@ impl Parser {
         self.index
     }
 
-    pub fn get_current_arg(&self) -> &str {
-        if self.index >= self.args.len() {
-            eprintln!(
-                "out of bounds: index={} argc={}",
-                self.index,
-                self.args.len()
-            );
-            process::exit(1);
-        }
-        &self.args[self.index]
+    pub fn get_current_arg(&self) -> Result<&OsStr, ParseError> {
+        self.check_bounds()?;
+        Ok(&self.args[self.index])
     }
 
-    pub fn current_arg_is(&self, what: &str) -> bool {
-        self.get_current_arg() == what
+    pub fn current_arg_is(&self, what: &str) -> Result<bool, ParseError> {
+        Ok(self.get_current_arg()? == what)
     }
 }
+
+// returns the value of "--name=value" if current is option arg.
+fn long_option_value<'a>(current: &'a [u8], arg: &str) -> Option<&'a [u8]> {
+    current
+        .strip_prefix(arg.as_bytes())
+        .and_then(|rest| rest.strip_prefix(b"="))
+}
@@ -0,0 +1,160 @@
+// Ported from: orig_src/Dirlist.hh and orig_src/Dirlist.cc
+// Copyright 2006-2017 Paul Dreik (earlier Paul Sundvall)
+// See LICENSE for further details.
+
+use crate::error::{Error, ErrorReport};
+use crate::fileinfo::FileInfo;
+use std::fs;
+use std::path::Path;
+
+const MAX_DEPTH: i32 = 50;
+
+/// Traverses the directories given on the command line and reports every
+/// regular file (and, if asked to, every symlink) that is found.
+pub struct Dirlist {
+    followsymlinks: bool,
+}
+
+impl Dirlist {
+    pub fn new(followsymlinks: bool) -> Self {
+        Dirlist { followsymlinks }
+    }
+
+    /// Finds all files below `dir`, calling `report` with a `FileInfo` for
+    /// each of them. Everything found is tagged with `cmdline_index` and the
+    /// depth below `dir` it was found at. Directories that can not be read,
+    /// or are nested too deep, are skipped and added to `errors`.
+    pub fn walk<F: FnMut(FileInfo)>(
+        &self,
+        dir: &Path,
+        cmdline_index: i32,
+        report: &mut F,
+        errors: &mut ErrorReport,
+    ) {
+        self.walk_level(dir, cmdline_index, 0, report, errors);
+    }
+
+    fn walk_level<F: FnMut(FileInfo)>(
+        &self,
+        dir: &Path,
+        cmdline_index: i32,
+        recursionlevel: i32,
+        report: &mut F,
+        errors: &mut ErrorReport,
+    ) {
+        rddebug!(
+            "Now in walk with dir={} and recursionlevel={}",
+            dir.display(),
+            recursionlevel
+        );
+
+        if recursionlevel >= MAX_DEPTH {
+            errors.push(Error::TooDeep {
+                path: dir.to_path_buf(),
+            });
+            return;
+        }
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                rddebug!("failed to open directory: {}", e);
+                // this can be due to rights, or because it is not a directory
+                self.handle_possible_file(dir, cmdline_index, recursionlevel, e, report, errors);
+                return;
+            }
+        };
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(source) => {
+                    errors.push(Error::ReadDir {
+                        path: dir.to_path_buf(),
+                        source,
+                    });
+                    continue;
+                }
+            };
+            // investigate what kind of file it is, without following symlinks.
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+            let path = entry.path();
+
+            if file_type.is_symlink() {
+                if self.followsymlinks {
+                    if path.is_dir() {
+                        self.walk_level(&path, cmdline_index, recursionlevel + 1, report, errors);
+                    } else {
+                        report(FileInfo::new(path, cmdline_index, recursionlevel));
+                    }
+                }
+            } else if file_type.is_dir() {
+                self.walk_level(&path, cmdline_index, recursionlevel + 1, report, errors);
+            } else if file_type.is_file() {
+                report(FileInfo::new(path, cmdline_index, recursionlevel));
+            }
+        }
+    }
+
+    // called for items that could not be opened as a directory, which is the
+    // case for plain files given on the command line and unreadable dirs.
+    fn handle_possible_file<F: FnMut(FileInfo)>(
+        &self,
+        possiblefile: &Path,
+        cmdline_index: i32,
+        recursionlevel: i32,
+        open_error: std::io::Error,
+        report: &mut F,
+        errors: &mut ErrorReport,
+    ) {
+        rddebug!(
+            "Now in handle_possible_file with name {} and recursionlevel {}",
+            possiblefile.display(),
+            recursionlevel
+        );
+
+        // investigate what kind of file it is, don't follow symlink
+        let meta = match fs::symlink_metadata(possiblefile) {
+            Ok(meta) => meta,
+            Err(source) => {
+                // probably file does not exist, or trouble with rights.
+                errors.push(Error::Stat {
+                    path: possiblefile.to_path_buf(),
+                    source,
+                });
+                return;
+            }
+        };
+
+        let file_type = meta.file_type();
+        if file_type.is_symlink() {
+            rddebug!("found symlink");
+            if self.followsymlinks {
+                report(FileInfo::new(
+                    possiblefile.to_path_buf(),
+                    cmdline_index,
+                    recursionlevel,
+                ));
+            }
+        } else if file_type.is_dir() {
+            errors.push(Error::ReadDir {
+                path: possiblefile.to_path_buf(),
+                source: open_error,
+            });
+        } else if file_type.is_file() {
+            rddebug!("it is a regular file");
+            report(FileInfo::new(
+                possiblefile.to_path_buf(),
+                cmdline_index,
+                recursionlevel,
+            ));
+        } else {
+            println!(
+                "Dirlist::handle_possible_file(): found something else than a dir or a regular file."
+            );
+        }
+    }
+}
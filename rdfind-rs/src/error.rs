@@ -0,0 +1,313 @@
+// See LICENSE for further details.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What went wrong with a file, and during which operation.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading a directory failed.
+    ReadDir { path: PathBuf, source: io::Error },
+    /// Getting the size, device and inode of a file failed.
+    Stat { path: PathBuf, source: io::Error },
+    /// Reading the contents of a file failed.
+    Read { path: PathBuf, source: io::Error },
+    /// The file is not the one listed in the results file any more.
+    Changed { path: PathBuf },
+    /// A duplicate was found to differ from its original when comparing
+    /// them byte by byte, so it was left alone.
+    Differs { path: PathBuf, original: PathBuf },
+    /// Deleting a duplicate failed.
+    Delete { path: PathBuf, source: io::Error },
+    /// Replacing a duplicate with a symlink to `target` failed.
+    Symlink {
+        path: PathBuf,
+        target: PathBuf,
+        source: io::Error,
+    },
+    /// Replacing a duplicate with a hard link to `target` failed.
+    Hardlink {
+        path: PathBuf,
+        target: PathBuf,
+        source: io::Error,
+    },
+    /// Replacing a duplicate with a copy-on-write clone of `target` failed.
+    Reflink {
+        path: PathBuf,
+        target: PathBuf,
+        source: io::Error,
+    },
+    /// Sharing the extents of a duplicate with `target` failed.
+    Dedupe {
+        path: PathBuf,
+        target: PathBuf,
+        source: io::Error,
+    },
+    /// Moving a file out of the way before replacing it failed.
+    MoveToTemporary { path: PathBuf, source: io::Error },
+    /// Moving a file back after a failed replacement failed, so it is
+    /// left as `temporary`.
+    Undo {
+        path: PathBuf,
+        temporary: PathBuf,
+        source: io::Error,
+    },
+    /// Removing a replaced file from its temporary name failed.
+    Unlink {
+        path: PathBuf,
+        temporary: PathBuf,
+        source: io::Error,
+    },
+    /// Writing the checksum cache failed.
+    SaveCache { path: PathBuf, source: io::Error },
+    /// Writing the results file failed.
+    WriteResults { path: PathBuf, source: io::Error },
+    /// The directory is nested too deep below the one given, so it was not
+    /// looked in.
+    TooDeep { path: PathBuf },
+    /// A duplicate in a results file does not belong to the original
+    /// listed before it, by identity or by size, or there is no original
+    /// before it. The whole group was left alone.
+    NoOriginal {
+        path: PathBuf,
+        original: Option<PathBuf>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The file the operation was done on.
+    pub fn path(&self) -> &Path {
+        match self {
+            Error::ReadDir { path, .. }
+            | Error::Stat { path, .. }
+            | Error::Read { path, .. }
+            | Error::Changed { path }
+            | Error::Differs { path, .. }
+            | Error::Delete { path, .. }
+            | Error::Symlink { path, .. }
+            | Error::Hardlink { path, .. }
+            | Error::Reflink { path, .. }
+            | Error::Dedupe { path, .. }
+            | Error::MoveToTemporary { path, .. }
+            | Error::Undo { path, .. }
+            | Error::Unlink { path, .. }
+            | Error::SaveCache { path, .. }
+            | Error::WriteResults { path, .. }
+            | Error::TooDeep { path }
+            | Error::NoOriginal { path, .. } => path,
+        }
+    }
+
+    /// The underlying io error, if there is one.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Error::Changed { .. }
+            | Error::Differs { .. }
+            | Error::TooDeep { .. }
+            | Error::NoOriginal { .. } => None,
+            Error::ReadDir { source, .. }
+            | Error::Stat { source, .. }
+            | Error::Read { source, .. }
+            | Error::Delete { source, .. }
+            | Error::Symlink { source, .. }
+            | Error::Hardlink { source, .. }
+            | Error::Reflink { source, .. }
+            | Error::Dedupe { source, .. }
+            | Error::MoveToTemporary { source, .. }
+            | Error::Undo { source, .. }
+            | Error::Unlink { source, .. }
+            | Error::SaveCache { source, .. }
+            | Error::WriteResults { source, .. } => Some(source),
+        }
+    }
+
+    /// Whether the file was gone when it was needed, for instance because
+    /// it was removed while scanning.
+    pub fn is_not_found(&self) -> bool {
+        self.io_error()
+            .is_some_and(|e| e.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadDir { path, source } => {
+                write!(f, "failed reading directory {:?}: {}", path, source)
+            }
+            Error::Stat { path, source } => write!(f, "failed reading {:?}: {}", path, source),
+            Error::Read { path, source } => {
+                write!(f, "failed reading contents of {:?}: {}", path, source)
+            }
+            Error::Changed { path } => write!(
+                f,
+                "{:?} changed since the results file was made, skipping it",
+                path
+            ),
+            Error::Differs { path, original } => write!(
+                f,
+                "{:?} differs from {:?}, leaving it alone",
+                path, original
+            ),
+            Error::Delete { path, source } => {
+                write!(f, "failed deleting file {:?}: {}", path, source)
+            }
+            Error::Symlink {
+                path,
+                target,
+                source,
+            } => write!(
+                f,
+                "failed to make symlink {:?} to {:?}: {}",
+                path, target, source
+            ),
+            Error::Hardlink {
+                path,
+                target,
+                source,
+            } => write!(
+                f,
+                "failed to make hardlink {:?} to {:?}: {}",
+                path, target, source
+            ),
+            Error::Reflink {
+                path,
+                target,
+                source,
+            } => write!(
+                f,
+                "failed to make reflink {:?} to {:?}: {}{}",
+                path,
+                target,
+                source,
+                sharing_hint(source)
+            ),
+            Error::Dedupe {
+                path,
+                target,
+                source,
+            } => write!(
+                f,
+                "failed to share extents of {:?} with {:?}: {}{}",
+                path,
+                target,
+                source,
+                sharing_hint(source)
+            ),
+            Error::MoveToTemporary { path, source } => write!(
+                f,
+                "failed moving {:?} to a temporary file: {}",
+                path, source
+            ),
+            Error::Undo {
+                path,
+                temporary,
+                source,
+            } => write!(
+                f,
+                "failed moving file from temporary {:?} back to {:?}: {}",
+                temporary, path, source
+            ),
+            Error::Unlink {
+                path,
+                temporary,
+                source,
+            } => write!(
+                f,
+                "failed unlinking temporary file {:?} made from {:?}: {}",
+                temporary, path, source
+            ),
+            Error::SaveCache { path, source } => {
+                write!(f, "failed saving checksum cache {:?}: {}", path, source)
+            }
+            Error::WriteResults { path, source } => {
+                write!(f, "failed writing results file {:?}: {}", path, source)
+            }
+            Error::TooDeep { path } => {
+                write!(f, "recursion limit exceeded at {:?}, skipping it", path)
+            }
+            Error::NoOriginal {
+                path,
+                original: Some(original),
+            } => write!(
+                f,
+                "{:?} is not a duplicate of {:?} listed before it, skipping the group",
+                path, original
+            ),
+            Error::NoOriginal {
+                path,
+                original: None,
+            } => write!(
+                f,
+                "{:?} has no original listed before it, skipping it",
+                path
+            ),
+        }
+    }
+}
+
+// explains the errors the kernel gives for sharing data between files.
+fn sharing_hint(source: &io::Error) -> &'static str {
+    match source.raw_os_error() {
+        Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL) => {
+            " (the file system does not support reflinks)"
+        }
+        Some(libc::EXDEV) => " (the files are on different file systems)",
+        _ => "",
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.io_error().map(|e| e as _)
+    }
+}
+
+/// The errors that happened during a run, in the order they happened.
+///
+/// Files that fail are skipped, so a run goes on after an error. The report
+/// tells afterwards which files were skipped and why.
+#[derive(Debug, Default)]
+pub struct ErrorReport {
+    errors: Vec<Error>,
+}
+
+impl ErrorReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, error: Error) {
+        rddebug!("{}", error);
+        self.errors.push(error);
+    }
+
+    /// Moves the errors of `other` to the end of this report.
+    pub fn append(&mut self, other: &mut ErrorReport) {
+        self.errors.append(&mut other.errors);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Error> {
+        self.errors.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ErrorReport {
+    type Item = &'a Error;
+    type IntoIter = std::slice::Iter<'a, Error>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.iter()
+    }
+}
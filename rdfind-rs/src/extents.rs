@@ -0,0 +1,269 @@
+// See LICENSE for further details.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+
+/// This is the last extent of the file.
+pub const FIEMAP_EXTENT_LAST: u32 = 0x0000_0001;
+/// The location of the data is not known yet, for instance because it is
+/// waiting to be written.
+pub const FIEMAP_EXTENT_UNKNOWN: u32 = 0x0000_0002;
+/// The data is stored together with the metadata, so there is no physical
+/// location to tell.
+pub const FIEMAP_EXTENT_DATA_INLINE: u32 = 0x0000_0200;
+/// The extent is shared with other files, as after a reflink.
+pub const FIEMAP_EXTENT_SHARED: u32 = 0x0000_2000;
+
+/// A range of a file and where it is stored on its device, as told by the
+/// FIEMAP ioctl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    /// Where the range starts in the file.
+    pub logical: u64,
+    /// Where the range starts on the device.
+    pub physical: u64,
+    pub length: u64,
+    /// FIEMAP_EXTENT_* flags.
+    pub flags: u32,
+}
+
+// struct fiemap from linux/fiemap.h, without the extents following it
+#[repr(C)]
+struct Fiemap {
+    fm_start: u64,
+    fm_length: u64,
+    fm_flags: u32,
+    fm_mapped_extents: u32,
+    fm_extent_count: u32,
+    fm_reserved: u32,
+}
+
+// struct fiemap_extent from linux/fiemap.h
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FiemapExtent {
+    fe_logical: u64,
+    fe_physical: u64,
+    fe_length: u64,
+    fe_reserved64: [u64; 2],
+    fe_flags: u32,
+    fe_reserved: [u32; 3],
+}
+
+// how many extents are asked for at a time
+const BATCH: usize = 32;
+
+#[repr(C)]
+struct FiemapRequest {
+    header: Fiemap,
+    extents: [FiemapExtent; BATCH],
+}
+
+// the request number of an ioctl both reading and writing a T, like _IOWR
+// in linux/ioctl.h. it is not taken from libc, since the type its _IOWR
+// gives differs between versions.
+#[cfg(not(any(
+    target_arch = "mips",
+    target_arch = "mips64",
+    target_arch = "powerpc",
+    target_arch = "powerpc64",
+    target_arch = "sparc",
+    target_arch = "sparc64"
+)))]
+const fn iowr<T>(ty: u32, nr: u32) -> u32 {
+    (3 << 30) | ((size_of::<T>() as u32) << 16) | (ty << 8) | nr
+}
+
+// these have three direction bits and 13 bits of size
+#[cfg(any(
+    target_arch = "mips",
+    target_arch = "mips64",
+    target_arch = "powerpc",
+    target_arch = "powerpc64",
+    target_arch = "sparc",
+    target_arch = "sparc64"
+))]
+const fn iowr<T>(ty: u32, nr: u32) -> u32 {
+    (6 << 29) | ((size_of::<T>() as u32) << 16) | (ty << 8) | nr
+}
+
+const FS_IOC_FIEMAP: u32 = iowr::<Fiemap>(b'f' as u32, 11);
+
+/// The extents of `file` from the start to the end, in file order. Holes
+/// have no extent. Fails with EOPNOTSUPP on file systems which do not
+/// support FIEMAP.
+pub fn extents(file: &File) -> io::Result<Vec<Extent>> {
+    let mut all = Vec::new();
+    let mut start = 0;
+    loop {
+        let batch = query(file, start, BATCH)?;
+        let Some(last) = batch.last() else {
+            return Ok(all);
+        };
+        let done = last.flags & FIEMAP_EXTENT_LAST != 0;
+        start = last.logical + last.length;
+        all.extend(batch);
+        if done {
+            return Ok(all);
+        }
+    }
+}
+
+/// Tells if all data described by `file` is shared with, and stored at the
+/// same place as, the data described by `original`, both being extent lists
+/// from `extents`. Extents may be split differently in the two lists.
+pub fn all_shared(file: &[Extent], original: &[Extent]) -> bool {
+    let placed = |e: &Extent| e.flags & (FIEMAP_EXTENT_UNKNOWN | FIEMAP_EXTENT_DATA_INLINE) == 0;
+    !file.is_empty()
+        && file
+            .iter()
+            .all(|e| e.flags & FIEMAP_EXTENT_SHARED != 0 && placed(e))
+        && original.iter().all(placed)
+        && merged(file) == merged(original)
+}
+
+// joins extents which continue each other both in the file and on the
+// device, leaving the logical, physical and length of each run.
+fn merged(extents: &[Extent]) -> Vec<(u64, u64, u64)> {
+    let mut runs: Vec<(u64, u64, u64)> = Vec::new();
+    for e in extents {
+        match runs.last_mut() {
+            Some((logical, physical, length))
+                if *logical + *length == e.logical && *physical + *length == e.physical =>
+            {
+                *length += e.length
+            }
+            _ => runs.push((e.logical, e.physical, e.length)),
+        }
+    }
+    runs
+}
+
+/// The first extent of `file`, or None if it has no data on the device.
+pub fn first_extent(file: &File) -> io::Result<Option<Extent>> {
+    Ok(query(file, 0, 1)?.into_iter().next())
+}
+
+// asks for up to count extents from start to the end of the file.
+fn query(file: &File, start: u64, count: usize) -> io::Result<Vec<Extent>> {
+    let mut request = FiemapRequest {
+        header: Fiemap {
+            fm_start: start,
+            fm_length: u64::MAX - start,
+            fm_flags: 0,
+            fm_mapped_extents: 0,
+            fm_extent_count: count.min(BATCH) as u32,
+            fm_reserved: 0,
+        },
+        extents: [FiemapExtent::default(); BATCH],
+    };
+    // SAFETY: request is a struct fiemap followed by room for the number of
+    // extents it says, which is what the ioctl reads and writes.
+    let ret = unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            FS_IOC_FIEMAP as _,
+            &mut request as *mut FiemapRequest,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let mapped = (request.header.fm_mapped_extents as usize).min(BATCH);
+    Ok(request.extents[..mapped]
+        .iter()
+        .map(|e| Extent {
+            logical: e.fe_logical,
+            physical: e.fe_physical,
+            length: e.fe_length,
+            flags: e.fe_flags,
+        })
+        .collect())
+}
+
+/// Makes `dest` share all data of `source` with the FICLONE ioctl, replacing
+/// what `dest` had. Fails with EOPNOTSUPP where the file system does not
+/// support reflinks, and with EXDEV when the files are on different file
+/// systems.
+pub fn clone_file(dest: &File, source: &File) -> io::Result<()> {
+    // SAFETY: FICLONE takes the descriptor of the source as its argument.
+    let ret = unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// struct file_dedupe_range from linux/fs.h, without the destinations
+// following it
+#[repr(C)]
+struct FileDedupeRange {
+    src_offset: u64,
+    src_length: u64,
+    dest_count: u16,
+    reserved1: u16,
+    reserved2: u32,
+}
+
+// struct file_dedupe_range_info from linux/fs.h
+#[repr(C)]
+struct FileDedupeRangeInfo {
+    dest_fd: i64,
+    dest_offset: u64,
+    bytes_deduped: u64,
+    status: i32,
+    reserved: u32,
+}
+
+#[repr(C)]
+struct DedupeRequest {
+    header: FileDedupeRange,
+    info: FileDedupeRangeInfo,
+}
+
+const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+
+const FIDEDUPERANGE: u32 = iowr::<FileDedupeRange>(0x94, 54);
+
+/// Makes the `len` bytes at `offset` in `dest` share the data of the same
+/// range in `source` with the FIDEDUPERANGE ioctl. The kernel compares the
+/// ranges first and leaves them alone if they differ, giving None. Else
+/// gives how many bytes were deduplicated, which may be fewer than asked
+/// for. `dest` keeps its inode, and may be open for reading only if the
+/// caller owns it.
+pub fn dedupe_range(source: &File, dest: &File, offset: u64, len: u64) -> io::Result<Option<u64>> {
+    let mut request = DedupeRequest {
+        header: FileDedupeRange {
+            src_offset: offset,
+            src_length: len,
+            dest_count: 1,
+            reserved1: 0,
+            reserved2: 0,
+        },
+        info: FileDedupeRangeInfo {
+            dest_fd: dest.as_raw_fd() as i64,
+            dest_offset: offset,
+            bytes_deduped: 0,
+            status: 0,
+            reserved: 0,
+        },
+    };
+    // SAFETY: request is a struct file_dedupe_range followed by the one
+    // struct file_dedupe_range_info it says it has.
+    let ret = unsafe {
+        libc::ioctl(
+            source.as_raw_fd(),
+            FIDEDUPERANGE as _,
+            &mut request as *mut DedupeRequest,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    match request.info.status {
+        FILE_DEDUPE_RANGE_DIFFERS => Ok(None),
+        status if status < 0 => Err(io::Error::from_raw_os_error(-status)),
+        _ => Ok(Some(request.info.bytes_deduped)),
+    }
+}
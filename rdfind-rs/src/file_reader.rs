@@ -0,0 +1,252 @@
+// See LICENSE for further details.
+
+use memmap2::{Advice, Mmap};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::Path;
+
+// what offsets, lengths and buffers are aligned to for O_DIRECT. the logical
+// block size of the device is at most this on anything common.
+const DIRECT_ALIGN: usize = 4096;
+
+/// How file contents are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadBackend {
+    /// Reads through the page cache. The default.
+    #[default]
+    Buffered,
+    /// Maps files into memory instead of copying them into a buffer.
+    Mmap,
+    /// Reads past the page cache with O_DIRECT, so other programs keep
+    /// their cached data. Where O_DIRECT is not supported, the pages read
+    /// are dropped from the cache afterwards with posix_fadvise.
+    Direct,
+}
+
+impl ReadBackend {
+    /// The name used for this backend on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            ReadBackend::Buffered => "buffered",
+            ReadBackend::Mmap => "mmap",
+            ReadBackend::Direct => "direct",
+        }
+    }
+
+    /// The inverse of `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            ReadBackend::Buffered,
+            ReadBackend::Mmap,
+            ReadBackend::Direct,
+        ]
+        .into_iter()
+        .find(|backend| backend.name() == name)
+    }
+}
+
+/// An open file, read with one of the backends.
+pub struct FileReader {
+    file: File,
+    backend: ReadBackend,
+    // mapped on first use
+    map: Option<Mmap>,
+    // whether the file is open with O_DIRECT, which falls back to dropping
+    // pages with posix_fadvise when unsupported
+    direct: bool,
+}
+
+impl FileReader {
+    pub fn open(path: &Path, backend: ReadBackend) -> io::Result<Self> {
+        let mut direct = false;
+        let file = if backend == ReadBackend::Direct {
+            match OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_DIRECT)
+                .open(path)
+            {
+                Ok(file) => {
+                    direct = true;
+                    file
+                }
+                // tmpfs and some others refuse O_DIRECT
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => File::open(path)?,
+                Err(e) => return Err(e),
+            }
+        } else {
+            File::open(path)?
+        };
+        Ok(FileReader {
+            file,
+            backend,
+            map: None,
+            direct,
+        })
+    }
+
+    /// The current size of the file.
+    pub fn size(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    /// Passes the `len` bytes starting at `offset` to `f`, in pieces at
+    /// most the size of `buffer`. Stops early at the end of the file, and
+    /// returns how many bytes were passed.
+    pub fn read_range<F>(
+        &mut self,
+        offset: u64,
+        len: u64,
+        buffer: &mut [u8],
+        mut f: F,
+    ) -> io::Result<u64>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        let done = match self.backend {
+            ReadBackend::Buffered => self.read_buffered(offset, len, buffer, &mut f)?,
+            ReadBackend::Mmap => self.read_mapped(offset, len, buffer.len(), &mut f)?,
+            ReadBackend::Direct if self.direct => self.read_direct(offset, len, buffer, &mut f)?,
+            ReadBackend::Direct => self.read_buffered(offset, len, buffer, &mut f)?,
+        };
+        if self.backend == ReadBackend::Direct && !self.direct {
+            self.drop_cached(offset, done);
+        }
+        Ok(done)
+    }
+
+    fn read_buffered(
+        &self,
+        offset: u64,
+        len: u64,
+        buffer: &mut [u8],
+        f: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<u64> {
+        let mut done = 0;
+        while done < len {
+            let want = buffer.len().min((len - done) as usize);
+            let n = match self.file.read_at(&mut buffer[..want], offset + done) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            f(&buffer[..n])?;
+            done += n as u64;
+        }
+        Ok(done)
+    }
+
+    fn read_mapped(
+        &mut self,
+        offset: u64,
+        len: u64,
+        piece: usize,
+        f: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<u64> {
+        if self.map.is_none() {
+            // SAFETY: the map is only read from. a file truncated by someone
+            // else while mapped raises SIGBUS, the same risk every program
+            // mapping files it does not own takes.
+            let map = unsafe { Mmap::map(&self.file)? };
+            // the advice is only a hint, so failing to give it is harmless
+            let _ = map.advise(Advice::Sequential);
+            self.map = Some(map);
+        }
+        let map = self.map.as_ref().unwrap();
+        let start = (offset as usize).min(map.len());
+        let end = start + (len.min((map.len() - start) as u64) as usize);
+        for data in map[start..end].chunks(piece.max(1)) {
+            f(data)?;
+        }
+        Ok((end - start) as u64)
+    }
+
+    // reads aligned blocks covering the range into an aligned part of
+    // buffer, then passes on the part which was asked for.
+    fn read_direct(
+        &mut self,
+        offset: u64,
+        len: u64,
+        buffer: &mut [u8],
+        f: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<u64> {
+        let mut owned;
+        let mut aligned = aligned_part(buffer);
+        if aligned.is_empty() {
+            // too small to hold an aligned block, use a buffer of our own
+            owned = vec![0u8; 2 * DIRECT_ALIGN];
+            aligned = aligned_part(&mut owned);
+        }
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done;
+            let skip = (pos % DIRECT_ALIGN as u64) as usize;
+            // whole blocks up to the end of the range, so a short range
+            // does not read a full buffer.
+            let remaining = (len - done).min(aligned.len() as u64) as usize;
+            let want = (skip + remaining)
+                .next_multiple_of(DIRECT_ALIGN)
+                .min(aligned.len());
+            let n = match self.file.read_at(&mut aligned[..want], pos - skip as u64) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) && done == 0 => {
+                    // the device wants a larger alignment. go on without
+                    // O_DIRECT instead.
+                    self.stop_direct()?;
+                    return self.read_buffered(offset, len, buffer, f);
+                }
+                Err(e) => return Err(e),
+            };
+            if n <= skip {
+                break;
+            }
+            let n = (n - skip).min((len - done) as usize);
+            f(&aligned[skip..skip + n])?;
+            done += n as u64;
+        }
+        Ok(done)
+    }
+
+    fn stop_direct(&mut self) -> io::Result<()> {
+        let fd = self.file.as_raw_fd();
+        // SAFETY: fcntl on a descriptor owned by self.file.
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_DIRECT) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        self.direct = false;
+        Ok(())
+    }
+
+    // tells the kernel the range will not be needed again, so it leaves the
+    // cache to others.
+    fn drop_cached(&self, offset: u64, len: u64) {
+        if len == 0 {
+            return;
+        }
+        // SAFETY: posix_fadvise on a descriptor owned by self.file. the
+        // advice is only a hint, so a failure is ignored.
+        unsafe {
+            libc::posix_fadvise(
+                self.file.as_raw_fd(),
+                offset as libc::off_t,
+                len as libc::off_t,
+                libc::POSIX_FADV_DONTNEED,
+            );
+        }
+    }
+}
+
+// the largest part of buffer which starts at an aligned address and is a
+// whole number of aligned blocks long. empty if there is none.
+fn aligned_part(buffer: &mut [u8]) -> &mut [u8] {
+    let start = buffer.as_ptr().align_offset(DIRECT_ALIGN);
+    if start >= buffer.len() {
+        return &mut [];
+    }
+    let blocks = (buffer.len() - start) / DIRECT_ALIGN;
+    &mut buffer[start..start + blocks * DIRECT_ALIGN]
+}
@@ -2,23 +2,46 @@
 // Copyright 2006-2017 Paul Dreik (earlier Paul Sundvall)
 // See LICENSE for further details.
 
+use crate::checksum::{Checksum, ChecksumType};
+use crate::checksum_cache::ChecksumCache;
+use crate::error::{Error, Result};
+use crate::extents::{self, Extent, FIEMAP_EXTENT_UNKNOWN};
+use crate::file_reader::{FileReader, ReadBackend};
+use crate::path_normalize::{normalize, normalize_resolving};
+use crate::undoable_unlink::UndoableUnlink;
 use std::env;
-use std::fs::{self, hard_link, File};
-use std::io::{self, Read, Seek, SeekFrom};
+use std::fs::{self, File, FileTimes, OpenOptions, hard_link};
+use std::io::{self, Read};
 
-use std::os::unix::fs::{MetadataExt, symlink};
-use std::path::PathBuf;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, fchown, symlink};
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ReadToBufferMode {
-    NotDefined = -1,
-    ReadFirstBytes = 0,
-    ReadLastBytes = 1,
-    CreateMd5Checksum = 2,
-    CreateSha1Checksum,
-    CreateSha256Checksum,
-    CreateSha512Checksum,
-    CreateXxh128Checksum,
+    NotDefined,
+    /// Reads this many bytes from the start of the file.
+    ReadFirstBytes(usize),
+    /// Reads this many bytes from the end of the file.
+    ReadLastBytes(usize),
+    CreateChecksum(ChecksumType),
+}
+
+impl From<ChecksumType> for ReadToBufferMode {
+    fn from(checksumtype: ChecksumType) -> Self {
+        ReadToBufferMode::CreateChecksum(checksumtype)
+    }
+}
+
+impl ReadToBufferMode {
+    /// The checksum this mode computes, if it computes one.
+    pub fn checksum_type(self) -> Option<ChecksumType> {
+        match self {
+            ReadToBufferMode::CreateChecksum(checksumtype) => Some(checksumtype),
+            ReadToBufferMode::NotDefined
+            | ReadToBufferMode::ReadFirstBytes(_)
+            | ReadToBufferMode::ReadLastBytes(_) => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -27,6 +50,37 @@ pub enum DupType {
     FirstOccurrence,
     WithinSameTree,
     OutsideTree,
+    /// A duplicate whose data is already stored once on the device, in
+    /// extents it shares with its original, as after a reflink.
+    SharedExtents,
+}
+
+/// How the symlinks made by `FileInfo::make_symlink` point at their target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkStyle {
+    /// An absolute path. The default.
+    #[default]
+    Absolute,
+    /// The shortest path from the directory of the link, so links within a
+    /// tree keep working when it is moved or mounted somewhere else.
+    Relative,
+}
+
+impl SymlinkStyle {
+    /// The name used for this style on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            SymlinkStyle::Absolute => "absolute",
+            SymlinkStyle::Relative => "relative",
+        }
+    }
+
+    /// The inverse of `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [SymlinkStyle::Absolute, SymlinkStyle::Relative]
+            .into_iter()
+            .find(|style| style.name() == name)
+    }
 }
 
 pub struct FileInfo {
@@ -36,12 +90,23 @@ pub struct FileInfo {
     pub cmdline_index: i32,
     pub depth: i32,
     pub identity: i64,
-    pub somebytes: [u8; 64],
+    /// The bytes read by the last ReadFirstBytes or ReadLastBytes, padded
+    /// with zeros if the file is shorter.
+    pub somebytes: Vec<u8>,
+    /// The digest made by the last CreateChecksum.
+    pub digest: Vec<u8>,
     pub stat_size: i64,
     pub stat_ino: u64,
     pub stat_dev: u64,
+    pub stat_mtime: i64,
+    pub stat_mtime_nsec: i64,
     pub is_file: bool,
     pub is_directory: bool,
+    /// Where the data of the file starts on its device, if known. See
+    /// `read_physical_offset`.
+    pub physical_offset: Option<u64>,
+    // read_physical_offset has looked already
+    physical_offset_read: bool,
 }
 
 impl FileInfo {
@@ -53,113 +118,300 @@ impl FileInfo {
             cmdline_index,
             depth,
             identity: 0,
-            somebytes: [0; 64],
+            somebytes: Vec::new(),
+            digest: Vec::new(),
             stat_size: 99999,
             stat_ino: 99999,
             stat_dev: 99999,
+            stat_mtime: 0,
+            stat_mtime_nsec: 0,
             is_file: false,
             is_directory: false,
+            physical_offset: None,
+            physical_offset_read: false,
         }
     }
 
-    pub fn read_file_info(&mut self) -> bool {
+    /// Looks up where the data of the file starts on its device with
+    /// FIEMAP. It is left unknown for files without data on the device yet,
+    /// and on file systems which do not support FIEMAP. It is only looked
+    /// up once, later calls keep what was found.
+    pub fn read_physical_offset(&mut self) {
+        if self.physical_offset_read {
+            return;
+        }
+        self.physical_offset_read = true;
+        self.physical_offset = File::open(&self.filename)
+            .and_then(|file| extents::first_extent(&file))
+            .ok()
+            .flatten()
+            .filter(|extent| extent.flags & FIEMAP_EXTENT_UNKNOWN == 0)
+            .map(|extent| extent.physical);
+    }
+
+    /// Tells if all data of this file is in extents shared with `original`,
+    /// given as the extents of the original. False where that can not be
+    /// found out, as on file systems which do not support FIEMAP.
+    pub fn shares_extents_with(&self, original: &[Extent]) -> bool {
+        File::open(&self.filename)
+            .and_then(|file| extents::extents(&file))
+            .is_ok_and(|own| extents::all_shared(&own, original))
+    }
+
+    /// Reads size, device and inode. On failure, they are all set to zero.
+    pub fn read_file_info(&mut self) -> Result<()> {
         match fs::metadata(&self.filename) {
             Ok(meta) => {
                 self.stat_size = meta.size() as i64;
                 self.stat_ino = meta.ino();
                 self.stat_dev = meta.dev();
+                self.stat_mtime = meta.mtime();
+                self.stat_mtime_nsec = meta.mtime_nsec();
                 self.is_file = meta.is_file();
                 self.is_directory = meta.is_dir();
-                true
+                Ok(())
             }
-            Err(e) => {
+            Err(source) => {
                 self.stat_size = 0;
                 self.stat_ino = 0;
                 self.stat_dev = 0;
+                self.stat_mtime = 0;
+                self.stat_mtime_nsec = 0;
                 self.is_file = false;
                 self.is_directory = false;
-                eprintln!("read_file_info: Error reading {:?}: {}", self.filename, e);
-                false
+                Err(Error::Stat {
+                    path: self.filename.clone(),
+                    source,
+                })
             }
         }
     }
 
+    /// Reads the bytes asked for by `filltype` into `somebytes`, or the
+    /// checksum into `digest`, using `backend`. Checksums are taken from
+    /// `cache` if it has them, and added to it otherwise.
     pub fn fill_with_bytes(
         &mut self,
         filltype: ReadToBufferMode,
         lasttype: ReadToBufferMode,
         buffer: &mut [u8],
+        backend: ReadBackend,
+        cache: Option<&ChecksumCache>,
+    ) -> Result<()> {
+        self.read_to_buffer(filltype, lasttype, buffer, backend, cache)
+            .map_err(|source| self.read_error(source))
+    }
+
+    fn read_to_buffer(
+        &mut self,
+        filltype: ReadToBufferMode,
+        lasttype: ReadToBufferMode,
+        buffer: &mut [u8],
+        backend: ReadBackend,
+        cache: Option<&ChecksumCache>,
     ) -> io::Result<()> {
         // If file is short, first bytes might be ALL bytes!
-        if lasttype != ReadToBufferMode::NotDefined {
-            if self.stat_size <= self.somebytes.len() as i64 {
-                // pointless to read - all bytes in the file are in the field
-                // somebytes, or checksum is calculated!
-                return Ok(());
-            }
+        if lasttype != ReadToBufferMode::NotDefined && self.stat_size <= self.somebytes.len() as i64
+        {
+            // pointless to read - all bytes in the file are in the field
+            // somebytes, or checksum is calculated!
+            return Ok(());
         }
-        self.somebytes.fill(0);
-        let mut file = File::open(&self.filename)?;
-        match filltype {
-            ReadToBufferMode::ReadFirstBytes => {
-                file.read_exact(&mut self.somebytes)?;
+        if let Some(cache) = cache
+            && let Some(checksumtype) = filltype.checksum_type()
+            && let Some(digest) = cache.get(self, checksumtype)
+        {
+            // the file is unchanged since the checksum was computed
+            self.digest = digest;
+            return Ok(());
+        }
+        let mut file = FileReader::open(&self.filename, backend)?;
+        // all files in a group have the same size, so the sample never
+        // needs to be longer than the file.
+        let sample_len = |len: usize| len.min(self.stat_size.max(0) as usize);
+        let checksumtype = match filltype {
+            ReadToBufferMode::ReadFirstBytes(len) => {
+                let len = sample_len(len);
+                self.somebytes = read_sample(&mut file, 0, len, buffer)?;
+                None
             }
-            ReadToBufferMode::ReadLastBytes => {
-                let len = self.somebytes.len() as u64;
-                let filesize = file.metadata()?.len();
-                if filesize >= len {
-                    file.seek(SeekFrom::End(-(len as i64)))?;
-                } else {
-                    file.seek(SeekFrom::Start(0))?;
-                }
-                file.read_exact(&mut self.somebytes)?;
+            ReadToBufferMode::ReadLastBytes(len) => {
+                let len = sample_len(len);
+                let offset = file.size()?.saturating_sub(len as u64);
+                self.somebytes = read_sample(&mut file, offset, len, buffer)?;
+                None
+            }
+            ReadToBufferMode::CreateChecksum(checksumtype) => Some(checksumtype),
+            ReadToBufferMode::NotDefined => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("does not know how to do that filltype: {:?}", filltype),
+                ));
             }
-            // For checksum modes, you would call out to a checksum module here.
-            // Placeholder: just fill with zeros.
-            _ => {
-                // TODO: integrate with checksum.rs
+        };
+
+        if let Some(checksumtype) = checksumtype {
+            let mut chk = Checksum::new(checksumtype);
+            file.read_range(0, u64::MAX, buffer, |data| chk.update(data))?;
+
+            self.digest = chk.finalize_to_vec();
+            if let Some(cache) = cache {
+                cache.insert(self, checksumtype, &self.digest);
             }
         }
         Ok(())
     }
 
-    pub fn delete_file(&self) -> io::Result<()> {
-        match fs::remove_file(&self.filename) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                eprintln!("Failed deleting file {:?}: {}", self.filename, e);
-                Err(e)
+    /// Feeds `len` bytes starting at `offset` to `chk`, reading them in
+    /// pieces the size of `buffer` with `backend`.
+    pub fn checksum_range(
+        &self,
+        offset: u64,
+        len: u64,
+        buffer: &mut [u8],
+        backend: ReadBackend,
+        chk: &mut Checksum,
+    ) -> Result<()> {
+        checksum_file_range(&self.filename, offset, len, buffer, backend, chk)
+            .map_err(|source| self.read_error(source))
+    }
+
+    /// Compares this file byte by byte with `original`, giving
+    /// `Error::Differs` if they are not identical.
+    pub fn verify_identical(&self, original: &FileInfo) -> Result<()> {
+        let mut file = File::open(&self.filename).map_err(|source| self.read_error(source))?;
+        let mut other =
+            File::open(&original.filename).map_err(|source| original.read_error(source))?;
+        let mut buffer = vec![0u8; 1 << 16];
+        let mut otherbuffer = vec![0u8; 1 << 16];
+        loop {
+            let n = read_up_to(&mut file, &mut buffer).map_err(|source| self.read_error(source))?;
+            let m = read_up_to(&mut other, &mut otherbuffer)
+                .map_err(|source| original.read_error(source))?;
+            if buffer[..n] != otherbuffer[..m] {
+                return Err(Error::Differs {
+                    path: self.filename.clone(),
+                    original: original.filename.clone(),
+                });
+            }
+            if n < buffer.len() {
+                return Ok(());
             }
         }
     }
 
-    pub fn make_symlink(&self, target: &FileInfo) -> io::Result<()> {
-        let mut target_path = target.filename.clone();
-        make_absolute(&mut target_path)?;
-        simplify_path(&mut target_path);
-        match symlink(&target_path, &self.filename) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                eprintln!(
-                    "Failed to make symlink {:?} to {:?}: {}",
-                    self.filename, target.filename, e
-                );
-                Err(e)
-            }
+    fn read_error(&self, source: io::Error) -> Error {
+        Error::Read {
+            path: self.filename.clone(),
+            source,
         }
     }
 
-    pub fn make_hardlink(&self, target: &FileInfo) -> io::Result<()> {
-        match hard_link(&target.filename, &self.filename) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                eprintln!(
-                    "Failed to make hardlink {:?} to {:?}: {}",
-                    self.filename, target.filename, e
-                );
-                Err(e)
+    pub fn delete_file(&self) -> Result<()> {
+        fs::remove_file(&self.filename).map_err(|source| Error::Delete {
+            path: self.filename.clone(),
+            source,
+        })
+    }
+
+    /// Replaces this file with a symlink pointing to `target`, written as
+    /// told by `style`.
+    pub fn make_symlink(&self, target: &FileInfo, style: SymlinkStyle) -> Result<()> {
+        transactional_operation(&self.filename, |filename| {
+            // The path must be correct as seen from the directory where self
+            // is.
+            let target_path = match style {
+                // ".." only means the parent directory once the symlinks
+                // before it are resolved
+                SymlinkStyle::Absolute if has_parent_dir(&target.filename) => {
+                    normalize_resolving(&target.filename)?
+                }
+                SymlinkStyle::Absolute => {
+                    // Making the path absolute solves this problem.
+                    let mut target_path = target.filename.clone();
+                    make_absolute(&mut target_path)?;
+                    // clean up the path, so it does not contain "/./", "//"
+                    // or a trailing slash
+                    normalize(&target_path)
+                }
+                SymlinkStyle::Relative => relative_path(filename, &target.filename)?,
+            };
+            symlink(&target_path, filename)
+        })?
+        .map_err(|source| Error::Symlink {
+            path: self.filename.clone(),
+            target: target.filename.clone(),
+            source,
+        })
+    }
+
+    /// Replaces this file with a hard link to `target`.
+    pub fn make_hardlink(&self, target: &FileInfo) -> Result<()> {
+        transactional_operation(&self.filename, |filename| {
+            hard_link(&target.filename, filename)
+        })?
+        .map_err(|source| Error::Hardlink {
+            path: self.filename.clone(),
+            target: target.filename.clone(),
+            source,
+        })
+    }
+
+    /// Replaces this file with a copy-on-write clone of `target`. The clone
+    /// shares the data of `target`, but keeps the permissions, owner and
+    /// times of this file, and later changes to either do not affect the
+    /// other. Needs a file system with reflinks, like Btrfs or XFS.
+    pub fn make_reflink(&self, target: &FileInfo) -> Result<()> {
+        let meta = fs::metadata(&self.filename).map_err(|source| Error::Stat {
+            path: self.filename.clone(),
+            source,
+        })?;
+        transactional_operation(&self.filename, |filename| {
+            reflink(&target.filename, filename, &meta)
+        })?
+        .map_err(|source| Error::Reflink {
+            path: self.filename.clone(),
+            target: target.filename.clone(),
+            source,
+        })
+    }
+
+    /// Makes this file share the extents of `original` without replacing
+    /// it, so it keeps its inode, hard links and open handles. The kernel
+    /// compares the data itself, giving `Error::Differs` if it is not the
+    /// same. Returns the number of bytes deduplicated, which may be fewer
+    /// than the size of the file if the kernel left some of it alone.
+    pub fn dedupe_extents(&self, original: &FileInfo) -> Result<u64> {
+        let dedupe_error = |source| Error::Dedupe {
+            path: self.filename.clone(),
+            target: original.filename.clone(),
+            source,
+        };
+        let source = File::open(&original.filename).map_err(|e| original.read_error(e))?;
+        let dest = File::open(&self.filename).map_err(|e| self.read_error(e))?;
+        let size = dest.metadata().map_err(|e| self.read_error(e))?.len();
+        let mut offset = 0;
+        let mut deduped = 0;
+        while offset < size {
+            // the kernel does at most a few MiB per call anyway
+            let len = (size - offset).min(MAX_DEDUPE_CHUNK);
+            match extents::dedupe_range(&source, &dest, offset, len).map_err(dedupe_error)? {
+                None => {
+                    return Err(Error::Differs {
+                        path: self.filename.clone(),
+                        original: original.filename.clone(),
+                    });
+                }
+                // nothing more will be shared, for instance a tail which
+                // is not a whole block
+                Some(0) => break,
+                Some(n) => {
+                    offset += n;
+                    deduped += n;
+                }
             }
         }
+        Ok(deduped)
     }
 
     pub fn get_duptype_string(&self) -> &'static str {
@@ -168,19 +420,142 @@ impl FileInfo {
             DupType::FirstOccurrence => "DUPTYPE_FIRST_OCCURRENCE",
             DupType::WithinSameTree => "DUPTYPE_WITHIN_SAME_TREE",
             DupType::OutsideTree => "DUPTYPE_OUTSIDE_TREE",
+            DupType::SharedExtents => "DUPTYPE_SHARED_EXTENTS",
+        }
+    }
+
+    /// The inverse of `get_duptype_string`, for reading back results files.
+    pub fn parse_duptype_string(s: &str) -> Option<DupType> {
+        match s {
+            "DUPTYPE_UNKNOWN" => Some(DupType::Unknown),
+            "DUPTYPE_FIRST_OCCURRENCE" => Some(DupType::FirstOccurrence),
+            "DUPTYPE_WITHIN_SAME_TREE" => Some(DupType::WithinSameTree),
+            "DUPTYPE_OUTSIDE_TREE" => Some(DupType::OutsideTree),
+            "DUPTYPE_SHARED_EXTENTS" => Some(DupType::SharedExtents),
+            _ => None,
         }
     }
 }
 
-fn simplify_path(path: &mut PathBuf) {
-    let mut s = path.to_string_lossy().to_string();
-    while let Some(pos) = s.find("/./") {
-        s.replace_range(pos..pos + 3, "/");
+// Moves filename to a temporary, invokes f with filename as argument and then
+// deletes the temporary. In case of failure at any point, the temporary is
+// moved back to filename.
+//
+// The outer error is from moving the file around, the inner one from f.
+fn transactional_operation<F>(filename: &Path, f: F) -> Result<io::Result<()>>
+where
+    F: FnOnce(&Path) -> io::Result<()>,
+{
+    // move the file to a temporary.
+    let mut restorer = UndoableUnlink::new(filename)?;
+
+    // apply whatever the caller wanted.
+    if let Err(e) = f(filename) {
+        // roll back. not getting the file back is the worse problem.
+        restorer.undo()?;
+        return Ok(Err(e));
     }
-    while let Some(pos) = s.find("//") {
-        s.replace_range(pos..pos + 2, "/");
+
+    // operation succeeded, go ahead and unlink the temporary.
+    restorer.unlink()?;
+    Ok(Ok(()))
+}
+
+const MAX_DEDUPE_CHUNK: u64 = 16 << 20;
+
+// makes filename a clone of target, with the permissions, owner and times
+// in meta.
+fn reflink(target: &Path, filename: &Path, meta: &fs::Metadata) -> io::Result<()> {
+    let source = File::open(target)?;
+    let clone = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(filename)?;
+    extents::clone_file(&clone, &source)?;
+    // the owner goes first, changing it clears the setuid and setgid bits
+    let cloned = clone.metadata()?;
+    if (cloned.uid(), cloned.gid()) != (meta.uid(), meta.gid()) {
+        fchown(&clone, Some(meta.uid()), Some(meta.gid()))?;
+    }
+    clone.set_permissions(meta.permissions())?;
+    clone.set_times(
+        FileTimes::new()
+            .set_accessed(meta.accessed()?)
+            .set_modified(meta.modified()?),
+    )
+}
+
+fn checksum_file_range(
+    filename: &Path,
+    offset: u64,
+    len: u64,
+    buffer: &mut [u8],
+    backend: ReadBackend,
+    chk: &mut Checksum,
+) -> io::Result<()> {
+    let mut file = FileReader::open(filename, backend)?;
+    let done = file.read_range(offset, len, buffer, |data| chk.update(data))?;
+    // a file shorter than it was is an error, not a shorter checksum
+    if done < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+// reads len bytes at offset. a file ending before that leaves the remainder
+// zeroed.
+fn read_sample(
+    file: &mut FileReader,
+    offset: u64,
+    len: usize,
+    buffer: &mut [u8],
+) -> io::Result<Vec<u8>> {
+    let mut sample = vec![0; len];
+    let mut filled = 0;
+    file.read_range(offset, len as u64, buffer, |data| {
+        sample[filled..filled + data.len()].copy_from_slice(data);
+        filled += data.len();
+        Ok(())
+    })?;
+    Ok(sample)
+}
+
+// reads until buf is full or the end of file is reached, so files shorter
+// than buf leave the remainder zeroed instead of failing.
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
     }
-    *path = PathBuf::from(s);
+    Ok(filled)
+}
+
+// the shortest path leading from the directory of link to target. the
+// directories are resolved first, since ".." after a symlinked directory in
+// the link leads to the parent of where the symlink points, not to the
+// directory the symlink is in.
+fn relative_path(link: &Path, target: &Path) -> io::Result<PathBuf> {
+    let link = normalize_resolving(link)?;
+    let target = normalize_resolving(target)?;
+    let from = link.parent().unwrap_or(Path::new("/"));
+    let common = from
+        .components()
+        .zip(target.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut path: PathBuf = from.components().skip(common).map(|_| "..").collect();
+    path.extend(target.components().skip(common));
+    Ok(path)
+}
+
+fn has_parent_dir(path: &Path) -> bool {
+    path.components().any(|c| c == Component::ParentDir)
 }
 
 fn make_absolute(path: &mut PathBuf) -> io::Result<()> {
@@ -0,0 +1,32 @@
+// See LICENSE for further details.
+
+//! Finds duplicate files, the library behind the rdfind command.
+//!
+//! `scanner::Scanner` runs the whole search and returns the duplicates
+//! grouped by original. The building blocks it uses are public as well:
+//! `fileinfo::FileInfo` for a single file, `rdutil::Rdutil` for the
+//! elimination stages and the delete and link actions, `checksum::Checksum`
+//! for hashing, `file_reader::FileReader` for reading file contents and
+//! `undoable_unlink::UndoableUnlink` for replacing a file without losing it
+//! on failure.
+//!
+//! Files that can not be read, deleted or linked are skipped rather than
+//! ending the run. What went wrong with each is collected in an
+//! `error::ErrorReport`.
+
+#[macro_use]
+mod rdfind_debug;
+
+pub mod checksum;
+pub mod checksum_cache;
+pub mod cmdline_parser;
+pub mod dirlist;
+mod easy_random;
+pub mod error;
+pub mod extents;
+pub mod file_reader;
+pub mod fileinfo;
+pub mod path_normalize;
+pub mod rdutil;
+pub mod scanner;
+pub mod undoable_unlink;
@@ -3,42 +3,863 @@
 // Copyright 2006-2017 Paul Dreik (earlier Paul Sundvall)
 // See LICENSE for further details.
 
-mod checksum;
-mod cmdline_parser;
-// mod dirlist; // TODO: Implement dirlist.rs
-mod easy_random;
-mod fileinfo;
-mod rdutil;
-mod undoable_unlink;
-
-use cmdline_parser::Parser;
-use fileinfo::FileInfo;
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::path::PathBuf;
+use rdfind_rs::checksum::ChecksumType;
+use rdfind_rs::checksum_cache::ChecksumCache;
+use rdfind_rs::cmdline_parser::{ParseError, Parser};
+use rdfind_rs::error::{Error, ErrorReport};
+use rdfind_rs::file_reader::ReadBackend;
+use rdfind_rs::fileinfo::{DupType, FileInfo, SymlinkStyle};
+use rdfind_rs::rdutil::{self, Rdutil};
+use rdfind_rs::scanner::{Progress, Scanner};
+use std::env;
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::time::SystemTime;
+use std::time::Duration;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+// every file keeps its first or last bytes in memory, so bound them.
+const MAX_SAMPLESIZE: i64 = 128 << 20;
+
+fn usage() {
+    let indent = "                                  ";
+    println!("Usage: rdfind [scan] [options] FILE ...");
+    println!("       rdfind apply [options] RESULTSFILE");
+    println!("       rdfind report [RESULTSFILE]");
+    println!();
+    println!("Finds duplicate files recursively in the given FILEs (directories),");
+    println!("and takes appropriate action (by default, nothing).");
+    println!("Directories listed first are ranked higher, meaning that if a");
+    println!("file is found on several places, the file found in the directory first");
+    println!("encountered on the command line is kept, and the others are considered duplicate.");
+    println!();
+    println!("options are (default choice within parentheses)");
+    println!();
+    println!(" -ignoreempty      (true)| false  ignore empty files (true implies -minsize 1,");
+    println!("                                  false implies -minsize 0)");
+    println!(" -minsize N        (N=1)          ignores files with size less than N bytes");
+    println!(
+        " -maxsize N        (N=0)          ignores files with size N bytes and larger (use 0 to disable this check)."
+    );
+    println!(" -followsymlinks    true |(false) follow symlinks");
+    println!(" -removeidentinode (true)| false  ignore files with nonunique device and inode");
+    println!(" -checksum           md5 |(sha1)| sha256 | sha512 | xxh128 | blake3 | crc32c");
+    println!("{}checksum type. may be given several times, the", indent);
+    println!("{}checksums are then used in the order given,", indent);
+    println!("{}except crc32c which always goes first.", indent);
+    println!("{}xxh128 is very fast, but is noncryptographic.", indent);
+    println!("{}blake3 is fast and cryptographic.", indent);
+    println!("{}crc32c is only 32 bits. use it to weed out", indent);
+    println!("{}candidates before a stronger checksum, sha1 if", indent);
+    println!("{}no other is given.", indent);
+    println!(
+        "{}join checksums with + to compute them in one pass,",
+        indent
+    );
+    println!("{}files then have to match on all of them, for", indent);
+    println!("{}instance xxh128+sha256.", indent);
+    println!(" -firstbytes N     (N=64)         number of bytes at the start of files to");
+    println!("{}compare before the checksum, 0 skips it", indent);
+    println!(" -lastbytes N      (N=64)         number of bytes at the end of files to");
+    println!("{}compare before the checksum, 0 skips it", indent);
+    println!("{}can be up to {} MiB each.", indent, MAX_SAMPLESIZE >> 20);
+    println!(" -buffersize N");
+    println!(
+        "{}chunksize in bytes when calculating the checksum.",
+        indent
+    );
+    println!("{}The default is 1 MiB, can be up to 128 MiB.", indent);
+    println!(" -progressive       true |(false) compare files of the same size a chunk at a");
+    println!("{}time, dropping them as soon as they differ", indent);
+    println!(" -cache   true |(false)| clear");
+    println!(
+        "{}keep checksums in $XDG_CACHE_HOME/rdfind/checksums",
+        indent
+    );
+    println!(
+        "{}and skip reading files which did not change since.",
+        indent
+    );
+    println!("{}clear empties the cache before use.", indent);
+    println!(" -threads N        (N=1)          number of files to read at once. Files on");
+    println!("{}a rotating disk are read one at a time, those", indent);
+    println!("{}on NFS, tmpfs and other file systems without a", indent);
+    println!("{}disk of their own concurrently.", indent);
+    println!(" -readbackend (buffered)| mmap | direct");
+    println!(
+        "{}how to read files. direct bypasses the page cache,",
+        indent
+    );
+    println!("{}or drops what was read from it where O_DIRECT is", indent);
+    println!("{}not supported.", indent);
+    println!(" -deterministic    (true)| false  makes results independent of order");
+    println!("                                  from listing the filesystem");
+    println!(" -makesymlinks      true |(false) replace duplicate files with symbolic links");
+    println!(" -symlinkstyle (absolute)| relative");
+    println!("{}how symlinks point at the original. relative", indent);
+    println!("{}links keep working when the tree is moved", indent);
+    println!(" -makehardlinks     true |(false) replace duplicate files with hard links");
+    println!(" -makereflinks      true |(false) replace duplicate files with copy-on-write");
+    println!("{}clones, which need Btrfs, XFS or similar", indent);
+    println!(" -dedupeextents     true |(false) make duplicates share the data of their");
+    println!("{}original in place, keeping their inode. needs", indent);
+    println!("{}Btrfs, XFS or similar", indent);
+    println!(" -makeresultsfile  (true)| false  makes a results file");
+    println!(" -outputname  name  sets the results file name to \"name\" (default results.txt)");
+    println!(" -deleteduplicates  true |(false) delete duplicate files");
+    println!(" -sleep             Xms          sleep for X milliseconds between file reads.");
+    println!("                                  Default is 0. Only a few values");
+    println!("                                  are supported; 0,1-5,10,25,50,100");
+    println!(" -verify           true |(false) compare each duplicate byte by byte with");
+    println!("{}its original before deleting or linking it", indent);
+    println!(" -dryrun|-n         true |(false) print to stdout instead of changing anything");
+    println!(" -h|-help|--help                  show this help and exit");
+    println!(" -v|--version                     display version number and exit");
+    println!();
+    println!("Each option can also be given in GNU style, as in --delete-duplicates,");
+    println!("--make-hardlinks, --min-size=1M or --checksum=sha256. Long flags are true");
+    println!("unless given as --flag=false. Sizes may end in K, M, G or T.");
+    println!();
+    println!("commands are");
+    println!();
+    println!(" scan FILE ...                    find duplicates (the default)");
+    println!(" apply RESULTSFILE                delete or link the duplicates listed in");
+    println!("{}RESULTSFILE, as told by -deleteduplicates,", indent);
+    println!("{}-makehardlinks, -makereflinks, -makesymlinks or", indent);
+    println!("{}-dedupeextents. each duplicate is compared byte", indent);
+    println!("{}by byte with its original first", indent);
+    println!(" report [RESULTSFILE]             list the duplicates in RESULTSFILE");
+    println!("{}(default results.txt)", indent);
+    println!();
+    println!("To scan a directory named like a command, give it as ./scan.");
+    println!();
+    println!("Duplicates which already share all their data with the original, as");
+    println!("reflinks do, are marked DUPTYPE_SHARED_EXTENTS in the results file and");
+    println!("are not counted in the space that can be reduced.");
+    println!();
+    println!("Files that can not be read, deleted or linked are skipped and listed at");
+    println!("the end. The exit status is 0 on success, 1 for a bad command line and 2");
+    println!("if any file was skipped.");
+    println!();
+    println!("If properly installed, a man page should be available as man rdfind.");
+    println!();
+    println!(
+        "rdfind is written by Paul Dreik 2006 onwards. License: GPL v2 or later (at your option)."
+    );
+    println!("version is {}", VERSION);
+}
+
+struct Options {
+    makesymlinks: bool,           // turn duplicates into symbolic links
+    symlinkstyle: SymlinkStyle,   // how symlinks point at the original
+    makehardlinks: bool,          // turn duplicates into hard links
+    makereflinks: bool,           // turn duplicates into copy-on-write clones
+    dedupeextents: bool,          // share the extents of duplicates in place
+    makeresultsfile: bool,        // write a results file
+    minimumfilesize: i64,         // minimum file size to be noticed (0 - include empty files)
+    maximumfilesize: i64,         // if nonzero, files this size or larger are ignored
+    followsymlinks: bool,         // follow symlinks
+    deleteduplicates: bool,       // delete duplicate files
+    dryrun: bool,                 // only dryrun, don't destroy anything
+    verify: bool,                 // compare files byte by byte before acting on them
+    remove_identical_inode: bool, // remove files with identical inodes
+    checksums: Vec<ChecksumType>, // checksums to check for similarity with
+    deterministic: bool,          // be independent of filesystem order
+    firstbytes: usize,            // how many bytes to compare at the start
+    lastbytes: usize,             // how many bytes to compare at the end
+    buffersize: usize,            // chunksize to use when reading files
+    threads: usize,               // number of threads to read files with
+    cache: CacheUse,              // whether to use the checksum cache
+    readbackend: ReadBackend,     // how to read files
+    progressive: bool,            // compare files chunk by chunk
+    nsecsleep: u64,               // number of nanoseconds to sleep between each file read
+    resultsfile: PathBuf,         // results file name
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            makesymlinks: false,
+            symlinkstyle: SymlinkStyle::Absolute,
+            makehardlinks: false,
+            makereflinks: false,
+            dedupeextents: false,
+            makeresultsfile: true,
+            minimumfilesize: 1,
+            maximumfilesize: 0,
+            deleteduplicates: false,
+            followsymlinks: false,
+            dryrun: false,
+            verify: false,
+            remove_identical_inode: true,
+            checksums: Vec::new(),
+            deterministic: true,
+            firstbytes: 64,
+            lastbytes: 64,
+            buffersize: 1 << 20,
+            threads: 1,
+            cache: CacheUse::Bypass,
+            readbackend: ReadBackend::Buffered,
+            progressive: false,
+            nsecsleep: 0,
+            resultsfile: PathBuf::from("results.txt"),
+        }
+    }
+}
+
+// how to use the checksum cache, given with -cache.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CacheUse {
+    Bypass,
+    Use,
+    Clear,
+}
+
+// parses a numeric option value, exiting with a message if it is not a number.
+fn parse_number(arg: &str, value: &OsStr) -> i64 {
+    match value.to_str().map(str::parse) {
+        Some(Ok(number)) => number,
+        _ => {
+            eprintln!(
+                "expected a number after {}, not \"{}\"",
+                arg,
+                value.to_string_lossy()
+            );
+            process::exit(1);
+        }
+    }
+}
+
+// parses a size which may end in K, M, G or T, meaning multiples of 1024.
+fn parse_size(arg: &str, value: &OsStr) -> i64 {
+    let bytes = value.as_bytes();
+    let (digits, shift) = match bytes.last() {
+        Some(b'k' | b'K') => (&bytes[..bytes.len() - 1], 10),
+        Some(b'M') => (&bytes[..bytes.len() - 1], 20),
+        Some(b'G') => (&bytes[..bytes.len() - 1], 30),
+        Some(b'T') => (&bytes[..bytes.len() - 1], 40),
+        _ => (bytes, 0),
+    };
+    let size = std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse::<i64>().ok())
+        .and_then(|number| number.checked_mul(1 << shift));
+    match size {
+        Some(size) => size,
+        None => {
+            eprintln!(
+                "expected a number after {}, not \"{}\"",
+                arg,
+                value.to_string_lossy()
+            );
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_options(parser: &mut Parser) -> Result<Options, ParseError> {
+    let mut o = Options::default();
+    while parser.has_args_left() {
+        // empty strings are forbidden as input since they can not be file
+        // names or options
+        if parser.get_current_arg()?.is_empty() {
+            eprintln!("bad argument {}", parser.get_current_index());
+            process::exit(1);
+        }
+
+        // if we reach the end of the argument list - exit the loop and
+        // proceed with the file list instead.
+        if !parser.get_current_arg()?.as_bytes().starts_with(b"-") {
+            break;
+        }
+        // "--" ends the options, so files starting with - can be given.
+        if parser.current_arg_is("--")? {
+            parser.advance();
+            break;
+        }
+
+        if parser.try_parse_bool("-makesymlinks")? || parser.try_parse_flag("--make-symlinks")? {
+            o.makesymlinks = parser.get_parsed_bool();
+        } else if parser.try_parse_bool("-makehardlinks")?
+            || parser.try_parse_flag("--make-hardlinks")?
+        {
+            o.makehardlinks = parser.get_parsed_bool();
+        } else if parser.try_parse_bool("-makereflinks")?
+            || parser.try_parse_flag("--make-reflinks")?
+        {
+            o.makereflinks = parser.get_parsed_bool();
+        } else if parser.try_parse_bool("-dedupeextents")?
+            || parser.try_parse_bool("-dedupe-extents")?
+            || parser.try_parse_flag("--dedupe-extents")?
+        {
+            o.dedupeextents = parser.get_parsed_bool();
+        } else if parser.try_parse_bool("-makeresultsfile")?
+            || parser.try_parse_flag("--make-results-file")?
+        {
+            o.makeresultsfile = parser.get_parsed_bool();
+        } else if parser.try_parse_string("-outputname")?
+            || parser.try_parse_value("--output-name")?
+        {
+            o.resultsfile = PathBuf::from(parser.get_parsed_string());
+        } else if parser.try_parse_bool("-ignoreempty")?
+            || parser.try_parse_flag("--ignore-empty")?
+        {
+            o.minimumfilesize = if parser.get_parsed_bool() { 1 } else { 0 };
+        } else if parser.try_parse_string("-minsize")? || parser.try_parse_value("--min-size")? {
+            let minsize = parse_size("-minsize", parser.get_parsed_string());
+            if minsize < 0 {
+                eprintln!("negative value of minsize not allowed");
+                process::exit(1);
+            }
+            o.minimumfilesize = minsize;
+        } else if parser.try_parse_string("-maxsize")? || parser.try_parse_value("--max-size")? {
+            let maxsize = parse_size("-maxsize", parser.get_parsed_string());
+            if maxsize < 0 {
+                eprintln!("negative value of maxsize not allowed");
+                process::exit(1);
+            }
+            o.maximumfilesize = maxsize;
+        } else if parser.try_parse_bool("-deleteduplicates")?
+            || parser.try_parse_flag("--delete-duplicates")?
+        {
+            o.deleteduplicates = parser.get_parsed_bool();
+        } else if parser.try_parse_bool("-followsymlinks")?
+            || parser.try_parse_flag("--follow-symlinks")?
+        {
+            o.followsymlinks = parser.get_parsed_bool();
+        } else if parser.try_parse_bool("-dryrun")?
+            || parser.try_parse_bool("-n")?
+            || parser.try_parse_flag("--dry-run")?
+        {
+            o.dryrun = parser.get_parsed_bool();
+        } else if parser.try_parse_bool("-verify")? || parser.try_parse_flag("--verify")? {
+            o.verify = parser.get_parsed_bool();
+        } else if parser.try_parse_bool("-removeidentinode")?
+            || parser.try_parse_flag("--remove-identical-inode")?
+        {
+            o.remove_identical_inode = parser.get_parsed_bool();
+        } else if parser.try_parse_bool("-deterministic")?
+            || parser.try_parse_flag("--deterministic")?
+        {
+            o.deterministic = parser.get_parsed_bool();
+        } else if parser.try_parse_string("-checksum")? || parser.try_parse_value("--checksum")? {
+            let name = parser.get_parsed_string().to_string_lossy();
+            // checksums joined with + are computed in one pass
+            let parts = name
+                .split('+')
+                .map(ChecksumType::from_name)
+                .collect::<Option<Vec<_>>>();
+            let Some(parts) = parts else {
+                let names: Vec<&str> = ChecksumType::registered()
+                    .iter()
+                    .map(|t| t.name())
+                    .filter(|name| !name.contains('+'))
+                    .collect();
+                eprintln!("expected {}, not \"{}\"", names.join("/"), name);
+                process::exit(1);
+            };
+            match ChecksumType::combine(&parts) {
+                Ok(checksumtype) => {
+                    if !o.checksums.contains(&checksumtype) {
+                        o.checksums.push(checksumtype);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        } else if parser.try_parse_string("-firstbytes")?
+            || parser.try_parse_value("--first-bytes")?
+        {
+            let firstbytes = parse_size("-firstbytes", parser.get_parsed_string());
+            if firstbytes < 0 {
+                eprintln!("negative value of firstbytes not allowed");
+                process::exit(1);
+            } else if firstbytes > MAX_SAMPLESIZE {
+                eprintln!(
+                    "a maximum of {} MiB firstbytes is allowed, got {} MiB",
+                    MAX_SAMPLESIZE >> 20,
+                    firstbytes >> 20
+                );
+                process::exit(1);
+            }
+            o.firstbytes = firstbytes as usize;
+        } else if parser.try_parse_string("-lastbytes")?
+            || parser.try_parse_value("--last-bytes")?
+        {
+            let lastbytes = parse_size("-lastbytes", parser.get_parsed_string());
+            if lastbytes < 0 {
+                eprintln!("negative value of lastbytes not allowed");
+                process::exit(1);
+            } else if lastbytes > MAX_SAMPLESIZE {
+                eprintln!(
+                    "a maximum of {} MiB lastbytes is allowed, got {} MiB",
+                    MAX_SAMPLESIZE >> 20,
+                    lastbytes >> 20
+                );
+                process::exit(1);
+            }
+            o.lastbytes = lastbytes as usize;
+        } else if parser.try_parse_string("-buffersize")?
+            || parser.try_parse_value("--buffer-size")?
+        {
+            let buffersize = parse_number("-buffersize", parser.get_parsed_string());
+            const MAX_BUFFERSIZE: i64 = 128 << 20;
+            if buffersize <= 0 {
+                eprintln!("a negative or zero buffersize is not allowed");
+                process::exit(1);
+            } else if buffersize > MAX_BUFFERSIZE {
+                eprintln!(
+                    "a maximum of {} MiB buffersize is allowed, got {} MiB",
+                    MAX_BUFFERSIZE >> 20,
+                    buffersize >> 20
+                );
+                process::exit(1);
+            }
+            o.buffersize = buffersize as usize;
+        } else if parser.try_parse_bool("-progressive")?
+            || parser.try_parse_flag("--progressive")?
+        {
+            o.progressive = parser.get_parsed_bool();
+        } else if parser.try_parse_string("-cache")? || parser.try_parse_value("--cache")? {
+            o.cache = if parser.parsed_string_is("true") {
+                CacheUse::Use
+            } else if parser.parsed_string_is("false") {
+                CacheUse::Bypass
+            } else if parser.parsed_string_is("clear") {
+                CacheUse::Clear
+            } else {
+                eprintln!(
+                    "expected true, false or clear after -cache, not \"{}\"",
+                    parser.get_parsed_string().to_string_lossy()
+                );
+                process::exit(1);
+            };
+        } else if parser.try_parse_string("-symlinkstyle")?
+            || parser.try_parse_value("--symlink-style")?
+        {
+            let name = parser.get_parsed_string().to_string_lossy();
+            let Some(style) = SymlinkStyle::from_name(&name) else {
+                eprintln!(
+                    "expected absolute or relative after -symlinkstyle, not \"{}\"",
+                    name
+                );
+                process::exit(1);
+            };
+            o.symlinkstyle = style;
+        } else if parser.try_parse_string("-readbackend")?
+            || parser.try_parse_value("--read-backend")?
+        {
+            let name = parser.get_parsed_string().to_string_lossy();
+            let Some(backend) = ReadBackend::from_name(&name) else {
+                eprintln!(
+                    "expected buffered, mmap or direct after -readbackend, not \"{}\"",
+                    name
+                );
+                process::exit(1);
+            };
+            o.readbackend = backend;
+        } else if parser.try_parse_string("-threads")? || parser.try_parse_value("--threads")? {
+            let threads = parse_number("-threads", parser.get_parsed_string());
+            if threads <= 0 {
+                eprintln!("a negative or zero number of threads is not allowed");
+                process::exit(1);
+            }
+            o.threads = threads as usize;
+        } else if parser.try_parse_string("-sleep")? || parser.try_parse_value("--sleep")? {
+            o.nsecsleep = match parser.get_parsed_string().to_string_lossy().as_ref() {
+                "1ms" => 1000000,
+                "2ms" => 2000000,
+                "3ms" => 3000000,
+                "4ms" => 4000000,
+                "5ms" => 5000000,
+                "10ms" => 10000000,
+                "25ms" => 25000000,
+                "50ms" => 50000000,
+                "100ms" => 100000000,
+                nextarg => {
+                    eprintln!(
+                        "sorry, can only understand a few sleep values for now. \"{}\" is not among them.",
+                        nextarg
+                    );
+                    process::exit(1);
+                }
+            };
+        } else if parser.current_arg_is("-help")?
+            || parser.current_arg_is("-h")?
+            || parser.current_arg_is("--help")?
+        {
+            usage();
+            process::exit(0);
+        } else if parser.current_arg_is("-version")?
+            || parser.current_arg_is("--version")?
+            || parser.current_arg_is("-v")?
+        {
+            println!("This is rdfind version {}", VERSION);
+            process::exit(0);
+        } else {
+            eprintln!(
+                "did not understand option {}:\"{}\"",
+                parser.get_current_index(),
+                parser.get_current_arg()?.to_string_lossy()
+            );
+            process::exit(1);
+        }
+        parser.advance();
+    }
+
+    // fix default values
+    if o.maximumfilesize == 0 {
+        o.maximumfilesize = i64::MAX;
+    }
+
+    // verify conflicting arguments
+    if o.minimumfilesize >= o.maximumfilesize {
+        eprintln!(
+            "maximum filesize {} must be larger than minimum filesize {}",
+            o.maximumfilesize, o.minimumfilesize
+        );
+        process::exit(1);
+    }
+
+    // decide what checksum to use - if no checksum is set, force sha1!
+    if o.checksums.is_empty() {
+        o.checksums.push(ChecksumType::SHA1);
+    }
+    Ok(o)
+}
+
+// what to do, given as the first argument. without one, rdfind scans.
+enum Command {
+    Scan,
+    Apply,
+    Report,
+}
+
+fn parse_command(parser: &mut Parser) -> Result<Command, ParseError> {
+    let command = if parser.current_arg_is("scan")? {
+        Command::Scan
+    } else if parser.current_arg_is("apply")? {
+        Command::Apply
+    } else if parser.current_arg_is("report")? {
+        Command::Report
+    } else {
+        return Ok(Command::Scan);
+    };
+    parser.advance();
+    Ok(command)
+}
 
 fn main() {
-    // TODO: Port full logic from rdfind.cc
+    if env::args_os().len() == 1 {
+        usage();
+        return;
+    }
+
+    // parse the input arguments
+    let mut parser = Parser::new();
+    let (command, o) = parse_command(&mut parser)
+        .and_then(|command| Ok((command, parse_options(&mut parser)?)))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+
+    match command {
+        Command::Scan => scan(&mut parser, &o),
+        Command::Apply => apply(&mut parser, &o),
+        Command::Report => report(&mut parser, &o),
+    }
+}
+
+// finds duplicates among the files and directories left on the command line.
+fn scan(parser: &mut Parser, o: &Options) {
+    // set the dryrun string
+    let dryruntext = if o.dryrun { "(DRYRUN MODE) " } else { "" };
+
+    let mut scanner = Scanner::new()
+        .min_size(o.minimumfilesize)
+        .max_size(o.maximumfilesize)
+        .follow_symlinks(o.followsymlinks)
+        .remove_identical_inodes(o.remove_identical_inode)
+        .deterministic(o.deterministic)
+        .read_backend(o.readbackend)
+        .first_bytes(o.firstbytes)
+        .last_bytes(o.lastbytes)
+        .buffer_size(o.buffersize)
+        .threads(o.threads)
+        .progressive(o.progressive)
+        .sleep(Duration::from_nanos(o.nsecsleep));
+    for &checksumtype in &o.checksums {
+        scanner = scanner.checksum(checksumtype);
+    }
+    if o.cache != CacheUse::Bypass {
+        scanner = scanner.checksum_cache(open_cache(o.cache));
+    }
+
+    // done with arguments. the rest are files and directories!
+    while parser.has_args_left() {
+        // get the next arg, without trailing /
+        let mut file_or_dir = parser.get_current_arg().unwrap().as_bytes();
+        while file_or_dir.len() > 1 && file_or_dir.ends_with(b"/") {
+            file_or_dir = &file_or_dir[..file_or_dir.len() - 1];
+        }
+        let file_or_dir = Path::new(OsStr::from_bytes(file_or_dir));
+        let cmdline_index = parser.get_current_index() as i32;
+        scanner = scanner.root_with_priority(file_or_dir, cmdline_index);
+        parser.advance();
+    }
+
+    let mut duplicates = scanner.scan_with_progress(|progress| match progress {
+        Progress::Scanning { root } => {
+            print!("{}Now scanning \"{}\"", dryruntext, root.display());
+            let _ = io::stdout().flush();
+        }
+        Progress::Scanned { nfiles, .. } => println!(", found {} files.", nfiles),
+        Progress::Collected { nfiles } => {
+            println!("{}Now have {} files in total.", dryruntext, nfiles)
+        }
+        Progress::RemovedIdenticalInodes { removed } => println!(
+            "{}Removed {} files due to nonunique device and inode.",
+            dryruntext, removed
+        ),
+        Progress::TotalSize { bytes } => println!(
+            "{}Total size is {} bytes or {}",
+            dryruntext,
+            bytes,
+            rdutil::format_size(bytes)
+        ),
+        Progress::RemovedUniqueSizes { removed, left } => println!(
+            "Removed {} files due to unique sizes from list. {} files left.",
+            removed, left
+        ),
+        Progress::Eliminating { description } => {
+            print!(
+                "{}Now eliminating candidates based on {}: ",
+                dryruntext, description
+            );
+            let _ = io::stdout().flush();
+        }
+        Progress::Eliminated { removed, left } => {
+            println!("removed {} files from list. {} files left.", removed, left)
+        }
+    });
+    let mut errors = duplicates.take_errors();
+    let gswd = duplicates.rdutil();
+
+    println!(
+        "{}It seems like you have {} files that are not unique",
+        dryruntext,
+        gswd.list.len()
+    );
+
     println!(
-        "[STUB] rdfind main logic would go here. Directory traversal and duplicate detection not yet implemented."
+        "{}Totally, {} can be reduced.",
+        dryruntext,
+        gswd.saveable_space()
     );
-    // Log the porting event
-    let log_entry = format!(
-        "{} | Ported orig_src/rdfind.cc to rdfind-rs/src/main.rs\n",
-        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+    print_shared(&gswd, dryruntext);
+
+    // traverse the list and make a nice file with the results
+    if o.makeresultsfile {
+        println!(
+            "{}Now making results file {}",
+            dryruntext,
+            o.resultsfile.display()
+        );
+        if let Err(source) = gswd.print_to_file(&o.resultsfile) {
+            errors.push(Error::WriteResults {
+                path: o.resultsfile.clone(),
+                source,
+            });
+        }
+    }
+
+    take_action(&gswd, o, o.verify, &mut errors);
+    exit_on_errors(&errors);
+}
+
+// opens the checksum cache, emptying it first if asked to.
+fn open_cache(cache: CacheUse) -> ChecksumCache {
+    let Some(path) = ChecksumCache::default_path() else {
+        eprintln!("can not find a cache directory, set XDG_CACHE_HOME or HOME");
+        process::exit(1);
+    };
+    let opened = if cache == CacheUse::Clear {
+        ChecksumCache::clear(&path).and_then(|()| ChecksumCache::open(&path))
+    } else {
+        ChecksumCache::open(&path)
+    };
+    opened.unwrap_or_else(|e| {
+        eprintln!("could not open cache \"{}\": {}", path.display(), e);
+        process::exit(1);
+    })
+}
+
+// applies the action given on the command line to the duplicates in a
+// results file made by an earlier scan.
+fn apply(parser: &mut Parser, o: &Options) {
+    let resultsfile = results_file_argument(parser).unwrap_or_else(|| {
+        eprintln!("apply needs the name of a results file");
+        process::exit(1);
+    });
+    if !o.makesymlinks
+        && !o.makehardlinks
+        && !o.makereflinks
+        && !o.dedupeextents
+        && !o.deleteduplicates
+    {
+        eprintln!(
+            "nothing to apply, use -deleteduplicates, -makehardlinks, -makereflinks, -makesymlinks or -dedupeextents"
+        );
+        process::exit(1);
+    }
+
+    let dryruntext = if o.dryrun { "(DRYRUN MODE) " } else { "" };
+    let mut filelist: Vec<FileInfo> = Vec::new();
+    let mut gswd = Rdutil::new(&mut filelist);
+    let mut errors = ErrorReport::new();
+    let nread = read_results_file(&mut gswd, &resultsfile);
+    println!(
+        "{}Read {} files from \"{}\".",
+        dryruntext,
+        nread,
+        resultsfile.display()
+    );
+    println!(
+        "{}Removed {} files that changed since the results file was made.",
+        dryruntext,
+        gswd.remove_changed_files(&mut errors)
+    );
+    // a file may have been changed in place since the scan, keeping its size,
+    // device and inode, so the contents are always compared again
+    take_action(&gswd, o, true, &mut errors);
+    exit_on_errors(&errors);
+}
+
+// shows the duplicates in a results file made by an earlier scan.
+fn report(parser: &mut Parser, o: &Options) {
+    let resultsfile = results_file_argument(parser).unwrap_or_else(|| o.resultsfile.clone());
+    let mut filelist: Vec<FileInfo> = Vec::new();
+    let mut gswd = Rdutil::new(&mut filelist);
+    read_results_file(&mut gswd, &resultsfile);
+
+    let _ = gswd.print_groups(&mut io::stdout().lock());
+    println!(
+        "It seems like you have {} files that are not unique",
+        gswd.list.len()
     );
-    let log_path = "../porting_log.txt";
-    if let Ok(mut file) = OpenOptions::new().append(true).create(true).open(log_path) {
-        let _ = file.write_all(log_entry.as_bytes());
+    println!("Totally, {} can be reduced.", gswd.saveable_space());
+    print_shared(&gswd, "");
+}
+
+// tells how many duplicates were left out of the space that can be saved,
+// since they already share their data with their original.
+fn print_shared(gswd: &Rdutil, dryruntext: &str) {
+    let nshared = gswd
+        .list
+        .iter()
+        .filter(|f| f.duptype == DupType::SharedExtents)
+        .count();
+    if nshared > 0 {
+        println!(
+            "{}{} of the duplicates already share their data with the original, so they are not counted.",
+            dryruntext, nshared
+        );
     }
+}
+
+// takes the results file name from the command line, if there is one.
+fn results_file_argument(parser: &mut Parser) -> Option<PathBuf> {
+    if !parser.has_args_left() {
+        return None;
+    }
+    let resultsfile = PathBuf::from(parser.get_current_arg().unwrap());
+    parser.advance();
+    if parser.has_args_left() {
+        eprintln!("expected a single results file");
+        process::exit(1);
+    }
+    Some(resultsfile)
+}
+
+fn read_results_file(gswd: &mut Rdutil, resultsfile: &Path) -> usize {
+    gswd.read_from_file(resultsfile).unwrap_or_else(|e| {
+        eprintln!(
+            "could not read results file \"{}\": {}",
+            resultsfile.display(),
+            e
+        );
+        process::exit(1);
+    })
+}
+
+// deletes or links the marked duplicates, as asked for on the command line.
+// with verify, each duplicate is compared byte by byte with its original
+// first.
+fn take_action(gswd: &Rdutil, o: &Options, verify: bool, errors: &mut ErrorReport) {
+    let dryruntext = if o.dryrun { "(DRYRUN MODE) " } else { "" };
 
-    // Log the porting event for Rdutil.cc -> rdutil.rs
-    let log_entry =
-        format!("2025-05-05 11:35:12 | Ported orig_src/Rdutil.cc to rdfind-rs/src/rdutil.rs\n");
-    let log_path = "../porting_log.txt";
-    if let Ok(mut file) = OpenOptions::new().append(true).create(true).open(log_path) {
-        let _ = file.write_all(log_entry.as_bytes());
+    // traverse the list and replace with symlinks
+    if o.makesymlinks {
+        println!("{}Now making symbolic links. creating ", dryruntext);
+        let tmp = gswd.make_symlinks(o.dryrun, verify, o.symlinkstyle, errors);
+        println!("Making {} links.", tmp);
+        return;
+    }
+
+    // traverse the list and replace with hard links
+    if o.makehardlinks {
+        println!("{}Now making hard links.", dryruntext);
+        let tmp = gswd.make_hardlinks(o.dryrun, verify, errors);
+        println!("{}Making {} links.", dryruntext, tmp);
+        return;
+    }
+
+    // traverse the list and replace with reflinks
+    if o.makereflinks {
+        println!("{}Now making reflinks.", dryruntext);
+        let tmp = gswd.make_reflinks(o.dryrun, verify, errors);
+        println!("{}Making {} reflinks.", dryruntext, tmp);
+        return;
+    }
+
+    // traverse the list and share extents
+    if o.dedupeextents {
+        println!("{}Now deduplicating extents.", dryruntext);
+        let (nfiles, bytes) = gswd.dedupe_extents(o.dryrun, verify, errors);
+        if o.dryrun {
+            println!("{}Deduplicating {} files.", dryruntext, nfiles);
+        } else {
+            println!(
+                "Deduplicated {} in {} files.",
+                rdutil::format_size(bytes as i64),
+                nfiles
+            );
+        }
+        return;
+    }
+
+    // traverse the list and delete files
+    if o.deleteduplicates {
+        println!("{}Now deleting duplicates:", dryruntext);
+        let tmp = gswd.delete_duplicates(o.dryrun, verify, errors);
+        println!("{}Deleted {} files.", dryruntext, tmp);
+    }
+}
+
+// lists the files that were skipped, and exits with status 2 if there were
+// any.
+fn exit_on_errors(errors: &ErrorReport) {
+    if errors.is_empty() {
+        return;
+    }
+    eprintln!("{} files were skipped:", errors.len());
+    for e in errors {
+        eprintln!("{}", e);
     }
+    process::exit(2);
 }
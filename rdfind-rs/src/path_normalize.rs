@@ -0,0 +1,68 @@
+// See LICENSE for further details.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Normalises `path` one component at a time, so names which are not valid
+/// UTF-8 are kept as they are. Repeated slashes, `.` components and a
+/// trailing slash are dropped, and `..` removes the component before it. A
+/// `..` right after the root stays at the root, while one at the start of a
+/// relative path is kept. A relative path with nothing left is `.`.
+///
+/// The file system is not looked at. When the component removed by `..` is
+/// a symlink to a directory, the result is not the file the kernel would
+/// find, see `normalize_resolving` for that.
+///
+/// ```
+/// use rdfind_rs::path_normalize::normalize;
+/// use std::ffi::OsStr;
+/// use std::os::unix::ffi::OsStrExt;
+/// use std::path::Path;
+///
+/// assert_eq!(normalize(Path::new("/a//./b/../c/.")), Path::new("/a/c"));
+/// assert_eq!(normalize(Path::new("a/../../b/")), Path::new("../b"));
+/// assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+/// assert_eq!(normalize(Path::new("a/..")), Path::new("."));
+/// let latin1 = Path::new(OsStr::from_bytes(b"/caf\xe9/./menu"));
+/// assert_eq!(normalize(latin1).as_os_str().as_bytes(), b"/caf\xe9/menu");
+/// ```
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut parts: Vec<Component> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match parts.last() {
+                Some(Component::Normal(_)) => {
+                    parts.pop();
+                }
+                Some(Component::RootDir) => {}
+                _ => parts.push(component),
+            },
+            _ => parts.push(component),
+        }
+    }
+    if parts.is_empty() {
+        return PathBuf::from(".");
+    }
+    parts.iter().collect()
+}
+
+/// Like `normalize`, but resolves the directory the last component is in
+/// on the file system, following symlinks. The result is absolute and
+/// names the file the kernel finds from `path`. The last component is kept
+/// as is, so a symlink there is not followed, and it need not exist. Fails
+/// if the directory can not be resolved.
+pub fn normalize_resolving(path: &Path) -> io::Result<PathBuf> {
+    match path.components().next_back() {
+        Some(Component::Normal(name)) => {
+            let parent = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            Ok(fs::canonicalize(parent)?.join(name))
+        }
+        // the path ends in a directory, like "..", "." or "/"
+        _ => fs::canonicalize(path),
+    }
+}
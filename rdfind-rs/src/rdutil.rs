@@ -2,31 +2,65 @@
 // Copyright 2006-2017 Paul Dreik (earlier Paul Sundvall)
 // See LICENSE for further details.
 
-use crate::fileinfo::{DupType, FileInfo, ReadToBufferMode};
-use std::cmp::Ordering;
-use std::fs::File;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use crate::checksum::{Checksum, ChecksumType};
+use crate::checksum_cache::ChecksumCache;
+use crate::error::{Error, ErrorReport, Result};
+use crate::extents;
+use crate::file_reader::ReadBackend;
+use crate::fileinfo::{DupType, FileInfo, ReadToBufferMode, SymlinkStyle};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
 
+/// How `Rdutil::fill_with_bytes` reads files.
+#[derive(Clone, Copy)]
+pub struct ReadOptions<'c> {
+    /// The chunk size, in bytes, to read files in.
+    pub buffersize: usize,
+    /// How long to sleep after reading each file.
+    pub sleep: Duration,
+    /// How many threads to read files with.
+    pub threads: usize,
+    /// Where to look for checksums before reading a file.
+    pub cache: Option<&'c ChecksumCache>,
+    /// How to read the files.
+    pub backend: ReadBackend,
+}
+
+// the first chunk read by compare_progressively. each round reads twice as
+// much as the one before, up to MAX_CHUNK.
+const FIRST_CHUNK: u64 = 64 << 10;
+const MAX_CHUNK: u64 = 256 << 20;
+
 pub struct Rdutil<'a> {
     pub list: &'a mut Vec<FileInfo>,
+    // which devices are rotating disks, looked up once per device
+    rotational: HashMap<u64, bool>,
 }
 
 impl<'a> Rdutil<'a> {
     pub fn new(list: &'a mut Vec<FileInfo>) -> Self {
-        Rdutil { list }
+        Rdutil {
+            list,
+            rotational: HashMap::new(),
+        }
     }
 
-    pub fn print_to_file(&self, filename: &str) -> io::Result<()> {
-        let mut f = File::create(filename)?;
+    pub fn print_to_file(&self, filename: &Path) -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(filename)?);
         writeln!(f, "# Automatically generated")?;
         writeln!(f, "# duptype id depth size device inode priority name")?;
         for fi in self.list.iter() {
-            writeln!(
+            write!(
                 f,
-                "{} {} {} {} {} {} {} {}",
+                "{} {} {} {} {} {} {} ",
                 fi.get_duptype_string(),
                 fi.identity,
                 fi.depth,
@@ -34,24 +68,218 @@ impl<'a> Rdutil<'a> {
                 fi.stat_dev,
                 fi.stat_ino,
                 fi.cmdline_index,
-                fi.filename.display()
             )?;
+            // write the name as is, it need not be valid UTF-8
+            f.write_all(fi.filename.as_os_str().as_bytes())?;
+            writeln!(f)?;
         }
         writeln!(f, "# end of file")?;
+        f.flush()
+    }
+
+    /// Deletes the duplicates from the file system, returning how many
+    /// were deleted. Failures are added to `errors`.
+    ///
+    /// With `verify`, each duplicate is first compared byte by byte with its
+    /// original, and left alone if they differ. This guards against
+    /// checksum collisions. It is done in dry runs too.
+    pub fn delete_duplicates(&self, dryrun: bool, verify: bool, errors: &mut ErrorReport) -> usize {
+        if dryrun {
+            apply_action_on_file(self.list, verify, errors, dryrun_helper("delete ", None))
+        } else {
+            apply_action_on_file(self.list, verify, errors, |a, _| a.delete_file())
+        }
+    }
+
+    /// Replaces the duplicates with symlinks to their original, written as
+    /// told by `style`, see `delete_duplicates`.
+    pub fn make_symlinks(
+        &self,
+        dryrun: bool,
+        verify: bool,
+        style: SymlinkStyle,
+        errors: &mut ErrorReport,
+    ) -> usize {
+        if dryrun {
+            apply_action_on_file(
+                self.list,
+                verify,
+                errors,
+                dryrun_helper("symlink ", Some(" to ")),
+            )
+        } else {
+            apply_action_on_file(self.list, verify, errors, |a, b| a.make_symlink(b, style))
+        }
+    }
+
+    /// Replaces the duplicates with hard links to their original, see
+    /// `delete_duplicates`.
+    pub fn make_hardlinks(&self, dryrun: bool, verify: bool, errors: &mut ErrorReport) -> usize {
+        if dryrun {
+            apply_action_on_file(
+                self.list,
+                verify,
+                errors,
+                dryrun_helper("hardlink ", Some(" to ")),
+            )
+        } else {
+            apply_action_on_file(self.list, verify, errors, |a, b| a.make_hardlink(b))
+        }
+    }
+
+    /// Replaces the duplicates with copy-on-write clones of their original,
+    /// see `delete_duplicates`.
+    pub fn make_reflinks(&self, dryrun: bool, verify: bool, errors: &mut ErrorReport) -> usize {
+        if dryrun {
+            apply_action_on_file(
+                self.list,
+                verify,
+                errors,
+                dryrun_helper("reflink ", Some(" to ")),
+            )
+        } else {
+            apply_action_on_file(self.list, verify, errors, |a, b| a.make_reflink(b))
+        }
+    }
+
+    /// Makes the duplicates share the extents of their original in place,
+    /// see `delete_duplicates`. Returns the number of files deduplicated
+    /// and how many bytes the kernel shared, which is zero in a dry run.
+    pub fn dedupe_extents(
+        &self,
+        dryrun: bool,
+        verify: bool,
+        errors: &mut ErrorReport,
+    ) -> (usize, u64) {
+        if dryrun {
+            let n = apply_action_on_file(
+                self.list,
+                verify,
+                errors,
+                dryrun_helper("dedupe extents of ", Some(" with ")),
+            );
+            return (n, 0);
+        }
+        let mut deduped = 0;
+        let n = apply_action_on_file(self.list, verify, errors, |a, b| {
+            deduped += a.dedupe_extents(b)?;
+            Ok(())
+        });
+        (n, deduped)
+    }
+
+    /// Reads back a results file made by `print_to_file`, appending the
+    /// files in it to the list. Returns the number of files read.
+    pub fn read_from_file(&mut self, filename: &Path) -> io::Result<usize> {
+        let mut f = BufReader::new(File::open(filename)?);
+        let mut line = Vec::new();
+        let mut lineno = 0;
+        let mut nread = 0;
+        loop {
+            line.clear();
+            if f.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            lineno += 1;
+            if line.ends_with(b"\n") {
+                line.pop();
+            }
+            if line.is_empty() || line.starts_with(b"#") {
+                continue;
+            }
+            let fi = parse_results_line(&line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: malformed line", filename.display(), lineno),
+                )
+            })?;
+            if fi.duptype == DupType::Unknown {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}:{}: a file of unknown duplicate type",
+                        filename.display(),
+                        lineno
+                    ),
+                ));
+            }
+            self.list.push(fi);
+            nread += 1;
+        }
+        Ok(nread)
+    }
+
+    /// Removes files which changed since they were listed, so nothing is
+    /// done to a file that is not the one that was compared. If an original
+    /// changed, its duplicates are removed as well. The files that changed
+    /// are added to `errors`.
+    pub fn remove_changed_files(&mut self, errors: &mut ErrorReport) -> usize {
+        let mut original_changed = false;
+        for elem in self.list.iter_mut() {
+            let (size, dev, ino) = (elem.stat_size, elem.stat_dev, elem.stat_ino);
+            let changed = match elem.read_file_info() {
+                Err(e) => {
+                    errors.push(e);
+                    true
+                }
+                Ok(()) => {
+                    let changed = !elem.is_file
+                        || (elem.stat_size, elem.stat_dev, elem.stat_ino) != (size, dev, ino);
+                    if changed {
+                        errors.push(Error::Changed {
+                            path: elem.filename.clone(),
+                        });
+                    }
+                    changed
+                }
+            };
+            if elem.duptype == DupType::FirstOccurrence {
+                original_changed = changed;
+            }
+            elem.delete_flag = changed || original_changed;
+        }
+        self.cleanup()
+    }
+
+    /// Writes each original followed by its duplicates, indented.
+    pub fn print_groups<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for fi in self.list.iter() {
+            if fi.duptype != DupType::FirstOccurrence {
+                write!(out, "  ")?;
+            }
+            out.write_all(fi.filename.as_os_str().as_bytes())?;
+            writeln!(out)?;
+        }
         Ok(())
     }
 
     pub fn mark_items(&mut self) {
-        let mut fileno = 1;
-        for file in self.list.iter_mut() {
+        for (fileno, file) in (1..).zip(self.list.iter_mut()) {
             file.identity = fileno;
-            fileno += 1;
         }
     }
 
     pub fn sort_on_device_and_inode(&mut self) {
-        self.list
-            .sort_by(|a, b| (a.stat_dev, a.stat_ino).cmp(&(b.stat_dev, b.stat_ino)));
+        self.list.sort_by_key(|a| (a.stat_dev, a.stat_ino));
+    }
+
+    /// Sorts on device, then on where the data of each file starts on
+    /// rotating disks, so they are read in one sweep instead of seeking
+    /// back and forth. Files on other devices, and those whose position is
+    /// not known, come after in inode order.
+    pub fn sort_on_device_and_extent(&mut self) {
+        for file in self.list.iter_mut() {
+            if cached_is_rotational(&mut self.rotational, file.stat_dev) {
+                file.read_physical_offset();
+            }
+        }
+        self.list.sort_by_key(|a| {
+            (
+                a.stat_dev,
+                a.physical_offset.unwrap_or(u64::MAX),
+                a.stat_ino,
+            )
+        });
     }
 
     pub fn sort_on_depth_and_name(&mut self, index_of_first: usize) {
@@ -63,8 +291,7 @@ impl<'a> Rdutil<'a> {
     }
 
     pub fn remove_identical_inodes(&mut self) -> usize {
-        self.list
-            .sort_by(|a, b| (a.stat_dev, a.stat_ino).cmp(&(b.stat_dev, b.stat_ino)));
+        self.list.sort_by_key(|a| (a.stat_dev, a.stat_ino));
         let mut removed = 0;
         let mut i = 0;
         while i < self.list.len() {
@@ -77,16 +304,8 @@ impl<'a> Rdutil<'a> {
                 j += 1;
             }
             if j - i > 1 {
-                // Keep the one with the lowest cmdline_index, depth, identity
-                let best = (i..j)
-                    .min_by_key(|&k| {
-                        (
-                            self.list[k].cmdline_index,
-                            self.list[k].depth,
-                            self.list[k].identity,
-                        )
-                    })
-                    .unwrap();
+                // let the highest-ranking element not be deleted
+                let best = (i..j).min_by_key(|&k| rank(&self.list[k])).unwrap();
                 for k in i..j {
                     self.list[k].delete_flag = k != best;
                 }
@@ -98,7 +317,7 @@ impl<'a> Rdutil<'a> {
     }
 
     pub fn remove_unique_sizes(&mut self) -> usize {
-        self.list.sort_by(|a, b| a.stat_size.cmp(&b.stat_size));
+        self.list.sort_by_key(|a| a.stat_size);
         let mut removed = 0;
         let mut i = 0;
         while i < self.list.len() {
@@ -120,27 +339,569 @@ impl<'a> Rdutil<'a> {
         removed
     }
 
+    pub fn remove_uniq_size_and_buffer(&mut self) -> usize {
+        self.list.sort_by(|a, b| sort_key(a).cmp(&sort_key(b)));
+        let mut i = 0;
+        while i < self.list.len() {
+            let mut j = i + 1;
+            while j < self.list.len() && same_size_and_buffer(&self.list[i], &self.list[j]) {
+                j += 1;
+            }
+            // a unique combination of size and buffer can not have a duplicate
+            let unique = j - i == 1;
+            for k in i..j {
+                self.list[k].delete_flag = unique;
+            }
+            i = j;
+        }
+        self.cleanup()
+    }
+
+    /// Assumes the list is sorted on size and buffer, with all unique
+    /// files already removed. Marks each sequence of duplicates, placing the
+    /// highest ranked file (the original) first in its sequence.
+    ///
+    /// The original keeps its positive identity, while its duplicates get
+    /// the negated identity of the original so results.txt shows which file
+    /// each duplicate belongs to.
+    pub fn mark_duplicates(&mut self) {
+        debug_assert!(
+            self.list
+                .is_sorted_by(|a, b| { sort_key(a) <= sort_key(b) })
+        );
+        let mut i = 0;
+        while i < self.list.len() {
+            let mut j = i + 1;
+            while j < self.list.len() && same_size_and_buffer(&self.list[i], &self.list[j]) {
+                j += 1;
+            }
+            // everything in [i, j) has equal size and buffer, so all are
+            // duplicates. the one with the lowest rank is the original.
+            debug_assert!(j - i >= 2);
+            let orig = (i..j).min_by_key(|&k| rank(&self.list[k])).unwrap();
+            self.list.swap(i, orig);
+
+            let orig_identity = self.list[i].identity;
+            let orig_cmdline_index = self.list[i].cmdline_index;
+            self.list[i].duptype = DupType::FirstOccurrence;
+            for elem in &mut self.list[i + 1..j] {
+                elem.identity = -orig_identity;
+                elem.duptype = if elem.cmdline_index == orig_cmdline_index {
+                    DupType::WithinSameTree
+                } else {
+                    DupType::OutsideTree
+                };
+            }
+            i = j;
+        }
+    }
+
+    /// Marks the duplicates whose data is already shared with their
+    /// original, on copy-on-write file systems, as `DupType::SharedExtents`.
+    /// Nothing would be saved by removing them. Returns how many there are.
+    pub fn mark_shared_extents(&mut self) -> usize {
+        let mut nshared = 0;
+        for group in self
+            .list
+            .chunk_by_mut(|_, b| b.duptype != DupType::FirstOccurrence)
+        {
+            let (original, duplicates) = group.split_first_mut().unwrap();
+            let Ok(extents) =
+                File::open(&original.filename).and_then(|file| extents::extents(&file))
+            else {
+                continue;
+            };
+            for elem in duplicates {
+                if elem.stat_dev == original.stat_dev && elem.shares_extents_with(&extents) {
+                    elem.duptype = DupType::SharedExtents;
+                    nshared += 1;
+                }
+            }
+        }
+        nshared
+    }
+
     pub fn cleanup(&mut self) -> usize {
         let before = self.list.len();
         self.list.retain(|f| !f.delete_flag);
         before - self.list.len()
     }
 
+    /// Total size of all files in the list, in bytes.
+    pub fn total_size_in_bytes(&self) -> i64 {
+        self.list.iter().map(|f| f.stat_size).sum()
+    }
+
+    /// Size of the files that would be left if all duplicates were removed.
+    pub fn original_size_in_bytes(&self) -> i64 {
+        self.list
+            .iter()
+            .filter(|f| f.duptype == DupType::FirstOccurrence)
+            .map(|f| f.stat_size)
+            .sum()
+    }
+
+    /// Size of the duplicates which already share their data with their
+    /// original.
+    pub fn shared_size_in_bytes(&self) -> i64 {
+        self.list
+            .iter()
+            .filter(|f| f.duptype == DupType::SharedExtents)
+            .map(|f| f.stat_size)
+            .sum()
+    }
+
+    /// The total size formatted like "45 B" or "3 GiB".
+    pub fn total_size(&self) -> String {
+        format_size(self.total_size_in_bytes())
+    }
+
+    /// The amount of space that can be saved, formatted like `total_size`.
+    /// Duplicates already sharing their data with the original save nothing.
+    pub fn saveable_space(&self) -> String {
+        format_size(
+            self.total_size_in_bytes()
+                - self.original_size_in_bytes()
+                - self.shared_size_in_bytes(),
+        )
+    }
+
+    /// Reads bytes or a checksum of each file into its buffer, using up to
+    /// `options.threads` threads. Files that can not be read are removed from the
+    /// list, since a buffer that was not filled must not be compared, and
+    /// added to `errors`. Returns the number of files removed.
+    ///
+    /// Files on a rotating disk, or a device of unknown kind, are read one
+    /// at a time in the order their data is on the disk, or in inode order
+    /// where that is not known, to avoid seeking. Other devices have their
+    /// files read concurrently. Different devices are read in parallel.
     pub fn fill_with_bytes(
         &mut self,
         type_: ReadToBufferMode,
         lasttype: ReadToBufferMode,
-        nsecsleep: u64,
-        buffersize: usize,
+        options: &ReadOptions,
+        errors: &mut ErrorReport,
+    ) -> usize {
+        self.sort_on_device_and_extent();
+
+        // split the list into units of work. a unit is read in order by a
+        // single thread.
+        let mut units: Vec<&mut [FileInfo]> = Vec::new();
+        for device in self.list.chunk_by_mut(|a, b| a.stat_dev == b.stat_dev) {
+            if cached_is_rotational(&mut self.rotational, device[0].stat_dev) {
+                units.push(device);
+            } else {
+                units.extend(device.chunks_mut(1));
+            }
+        }
+
+        let nthreads = options.threads.clamp(1, units.len().max(1));
+        let queue = Mutex::new(units.into_iter());
+        let worker = || {
+            let mut buffer = vec![0u8; options.buffersize];
+            let mut failed = ErrorReport::new();
+            loop {
+                // hold the lock only while taking the next unit
+                let next = queue.lock().unwrap().next();
+                let Some(unit) = next else {
+                    break;
+                };
+                for elem in unit {
+                    if let Err(e) = elem.fill_with_bytes(
+                        type_,
+                        lasttype,
+                        &mut buffer,
+                        options.backend,
+                        options.cache,
+                    ) {
+                        elem.delete_flag = true;
+                        failed.push(e);
+                    }
+                    if !options.sleep.is_zero() {
+                        thread::sleep(options.sleep);
+                    }
+                }
+            }
+            failed
+        };
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..nthreads).map(|_| scope.spawn(worker)).collect();
+            for w in workers {
+                errors.append(&mut w.join().unwrap());
+            }
+        });
+        self.cleanup()
+    }
+
+    /// An alternative to computing the checksum of each file with
+    /// `fill_with_bytes`. Files of equal size and buffer are read together
+    /// in growing chunks, and split up as soon as the checksums of what was
+    /// read so far differ. Files left without a match are removed, so large
+    /// files which differ early on are not read to the end. The files read
+    /// to the end get the checksum of all of them as their digest, like
+    /// `fill_with_bytes` gives them.
+    ///
+    /// Returns the number of files removed. The ones that could not be read
+    /// are added to `errors`.
+    pub fn compare_progressively(
+        &mut self,
+        checksumtype: ChecksumType,
+        options: &ReadOptions,
+        errors: &mut ErrorReport,
+    ) -> usize {
+        self.list.sort_by(|a, b| sort_key(a).cmp(&sort_key(b)));
+        let mut start = 0;
+        while start < self.list.len() {
+            let mut end = start + 1;
+            while end < self.list.len() && same_size_and_buffer(&self.list[start], &self.list[end])
+            {
+                end += 1;
+            }
+            self.compare_group(start..end, checksumtype, options, errors);
+            start = end;
+        }
+        self.cleanup()
+    }
+
+    // compares the files in group, which all have the same size and buffer.
+    fn compare_group(
+        &mut self,
+        group: std::ops::Range<usize>,
+        checksumtype: ChecksumType,
+        options: &ReadOptions,
+        errors: &mut ErrorReport,
     ) {
-        self.sort_on_device_and_inode();
-        let duration = Duration::from_nanos(nsecsleep);
-        let mut buffer = vec![0u8; buffersize];
-        for elem in self.list.iter_mut() {
-            let _ = elem.fill_with_bytes(type_, lasttype, &mut buffer);
-            if nsecsleep > 0 {
-                thread::sleep(duration);
+        let size = self.list[group.start].stat_size as u64;
+        if group.len() < 2 {
+            self.list[group.start].delete_flag = true;
+            return;
+        }
+        // all bytes of short files are in somebytes already
+        if size <= self.list[group.start].somebytes.len() as u64 {
+            return;
+        }
+        // nothing needs to be read if all checksums are known
+        if let Some(cache) = options.cache {
+            let cached: Option<Vec<_>> = group
+                .clone()
+                .map(|i| cache.get(&self.list[i], checksumtype))
+                .collect();
+            if let Some(digests) = cached {
+                for (i, digest) in group.zip(digests) {
+                    self.list[i].digest = digest;
+                }
+                return;
+            }
+        }
+
+        // spinning disks are read one file at a time
+        let parallel = options.threads > 1
+            && self.list[group.clone()]
+                .iter()
+                .all(|f| !cached_is_rotational(&mut self.rotational, f.stat_dev));
+
+        // the files which still might be duplicates, split into sets which
+        // have been equal so far
+        let mut sets = vec![
+            group
+                .map(|index| Candidate {
+                    index,
+                    chk: Checksum::new(checksumtype),
+                    digest: Vec::new(),
+                    error: None,
+                })
+                .collect::<Vec<_>>(),
+        ];
+        let mut offset = 0;
+        let mut chunk = FIRST_CHUNK;
+        while offset < size && !sets.is_empty() {
+            let len = chunk.min(size - offset);
+            for set in sets.iter_mut() {
+                read_chunk(self.list, set, offset, len, options, parallel);
+            }
+            offset += len;
+            chunk = (chunk * 2).min(MAX_CHUNK);
+
+            // split the sets on what was read
+            let mut split: Vec<Vec<Candidate>> = Vec::new();
+            for mut set in sets {
+                set.sort_by(|a, b| a.digest.cmp(&b.digest));
+                let first = split.len();
+                for mut candidate in set {
+                    if let Some(e) = candidate.error.take() {
+                        self.list[candidate.index].delete_flag = true;
+                        errors.push(e);
+                        continue;
+                    }
+                    match split[first..].last_mut() {
+                        Some(last) if last[0].digest == candidate.digest => last.push(candidate),
+                        _ => split.push(vec![candidate]),
+                    }
+                }
+            }
+            // a file which diverged from all others can not be a duplicate
+            for set in split.iter().filter(|set| set.len() == 1) {
+                self.list[set[0].index].delete_flag = true;
             }
+            split.retain(|set| set.len() > 1);
+            sets = split;
         }
+
+        // what is left was read to the end, and the digests are those of
+        // the whole files
+        for candidate in sets.iter().flatten() {
+            let file = &mut self.list[candidate.index];
+            file.digest = candidate.digest.clone();
+            if let Some(cache) = options.cache {
+                cache.insert(file, checksumtype, &candidate.digest);
+            }
+        }
+    }
+}
+
+// a file being compared by compare_progressively
+struct Candidate {
+    index: usize,
+    // the checksum of what was read so far, and its digest
+    chk: Checksum,
+    digest: Vec<u8>,
+    error: Option<Error>,
+}
+
+// reads the next len bytes at offset of each candidate in set.
+fn read_chunk(
+    list: &[FileInfo],
+    set: &mut [Candidate],
+    offset: u64,
+    len: u64,
+    options: &ReadOptions,
+    parallel: bool,
+) {
+    let read = |candidates: &mut [Candidate]| {
+        let mut buffer = vec![0u8; options.buffersize];
+        for candidate in candidates {
+            let file = &list[candidate.index];
+            match file.checksum_range(
+                offset,
+                len,
+                &mut buffer,
+                options.backend,
+                &mut candidate.chk,
+            ) {
+                Ok(()) => candidate.digest = candidate.chk.clone().finalize_to_vec(),
+                Err(e) => candidate.error = Some(e),
+            }
+            if !options.sleep.is_zero() {
+                thread::sleep(options.sleep);
+            }
+        }
+    };
+    if !parallel {
+        read(set);
+        return;
+    }
+    let per_thread = set.len().div_ceil(options.threads);
+    thread::scope(|scope| {
+        for candidates in set.chunks_mut(per_thread) {
+            scope.spawn(|| read(candidates));
+        }
+    });
+}
+
+// applies f(duplicate, original) on every duplicate, which must already be
+// marked with mark_duplicates. returns how many times f succeeded, the
+// failures go to errors. with verify, f is only applied on duplicates which
+// are identical to their original.
+//
+// a results file may have been edited, so a group whose duplicates do not
+// all belong to the original before them is skipped as a whole. otherwise
+// a duplicate could be replaced by a link to a file it never matched.
+fn apply_action_on_file<F>(
+    list: &[FileInfo],
+    verify: bool,
+    errors: &mut ErrorReport,
+    mut f: F,
+) -> usize
+where
+    F: FnMut(&FileInfo, &FileInfo) -> Result<()>,
+{
+    let mut ntimesapplied = 0;
+    for group in list.chunk_by(|_, b| b.duptype != DupType::FirstOccurrence) {
+        let (original, duplicates) = group.split_first().unwrap();
+        if original.duptype != DupType::FirstOccurrence {
+            for elem in group {
+                errors.push(Error::NoOriginal {
+                    path: elem.filename.clone(),
+                    original: None,
+                });
+            }
+            continue;
+        }
+        if let Some(elem) = duplicates.iter().find(|d| !is_duplicate_of(d, original)) {
+            errors.push(Error::NoOriginal {
+                path: elem.filename.clone(),
+                original: Some(original.filename.clone()),
+            });
+            continue;
+        }
+        for elem in duplicates {
+            if verify && let Err(e) = elem.verify_identical(original) {
+                errors.push(e);
+                continue;
+            }
+            match f(elem, original) {
+                Ok(()) => ntimesapplied += 1,
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+    ntimesapplied
+}
+
+// tells if elem is marked as a duplicate of original, and is as large.
+fn is_duplicate_of(elem: &FileInfo, original: &FileInfo) -> bool {
+    matches!(
+        elem.duptype,
+        DupType::WithinSameTree | DupType::OutsideTree | DupType::SharedExtents
+    ) && elem.identity == -original.identity
+        && elem.stat_size == original.stat_size
+}
+
+// makes an action that only tells what it would have done, pretending to
+// succeed. the name of the original is printed after m2, if given.
+fn dryrun_helper(
+    m1: &'static str,
+    m2: Option<&'static str>,
+) -> impl Fn(&FileInfo, &FileInfo) -> Result<()> {
+    move |a, b| {
+        match m2 {
+            Some(m2) => println!(
+                "(DRYRUN MODE) {}{}{}{}",
+                m1,
+                a.filename.display(),
+                m2,
+                b.filename.display()
+            ),
+            None => println!("(DRYRUN MODE) {}{}", m1, a.filename.display()),
+        }
+        Ok(())
+    }
+}
+
+// parses "duptype id depth size device inode priority name" as written by
+// print_to_file. the name is the rest of the line, and may contain spaces.
+fn parse_results_line(line: &[u8]) -> Option<FileInfo> {
+    let mut fields = line.splitn(8, |&c| c == b' ');
+    let mut next = || std::str::from_utf8(fields.next()?).ok();
+    let duptype = FileInfo::parse_duptype_string(next()?)?;
+    let identity = next()?.parse().ok()?;
+    let depth = next()?.parse().ok()?;
+    let stat_size = next()?.parse().ok()?;
+    let stat_dev = next()?.parse().ok()?;
+    let stat_ino = next()?.parse().ok()?;
+    let cmdline_index = next()?.parse().ok()?;
+    let name = fields.next().filter(|name| !name.is_empty())?;
+
+    let mut fi = FileInfo::new(PathBuf::from(OsStr::from_bytes(name)), cmdline_index, depth);
+    fi.duptype = duptype;
+    fi.identity = identity;
+    fi.stat_size = stat_size;
+    fi.stat_dev = stat_dev;
+    fi.stat_ino = stat_ino;
+    fi.is_file = true;
+    Some(fi)
+}
+
+// compares rank as described in RANKING on the man page: the file found
+// from the earliest command line argument wins, then the one closest to the
+// root, then the one found first.
+fn rank(f: &FileInfo) -> (i32, i32, i64) {
+    (f.cmdline_index, f.depth, f.identity)
+}
+
+// tells if the block device dev is a rotating disk, according to sysfs.
+// block devices which are not found there are assumed to be rotating, so
+// they are not read in parallel.
+//
+// btrfs subvolumes, overlayfs, nfs, tmpfs and other file systems without a
+// block device of their own have an anonymous device with major number 0.
+// btrfs names the disk it is on in /proc/self/mountinfo, which is used
+// instead. the others are not on a local disk, and are read in parallel.
+fn is_rotational(dev: u64) -> bool {
+    let (major, minor) = device_numbers(dev);
+    if major == 0 {
+        return mounted_block_device(major, minor)
+            .is_some_and(|disk| device_numbers(disk).0 != 0 && is_rotational(disk));
+    }
+    let sysfs = format!("/sys/dev/block/{}:{}", major, minor);
+    // partitions have the queue of their disk in the parent directory
+    ["queue/rotational", "../queue/rotational"]
+        .iter()
+        .find_map(|queue| fs::read_to_string(Path::new(&sysfs).join(queue)).ok())
+        .is_none_or(|rotational| rotational.trim() != "0")
+}
+
+// is_rotational, looked up in cache first.
+fn cached_is_rotational(cache: &mut HashMap<u64, bool>, dev: u64) -> bool {
+    *cache.entry(dev).or_insert_with(|| is_rotational(dev))
+}
+
+// the major and minor number of dev, in the glibc encoding.
+fn device_numbers(dev: u64) -> (u64, u64) {
+    let major = ((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0x0fff);
+    let minor = ((dev >> 12) & 0xffff_ff00) | (dev & 0x00ff);
+    (major, minor)
+}
+
+// the block device the file system with device major:minor is mounted from,
+// if it is mounted from one. lines of mountinfo look like
+// "36 35 0:45 / /mnt rw,noatime shared:1 - btrfs /dev/sda2 rw,ssd".
+fn mounted_block_device(major: u64, minor: u64) -> Option<u64> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    let wanted = format!("{}:{}", major, minor);
+    let line = mountinfo
+        .lines()
+        .find(|line| line.split(' ').nth(2) == Some(wanted.as_str()))?;
+    let (_, after) = line.split_once(" - ")?;
+    let source = after.split(' ').nth(1)?;
+    let meta = fs::metadata(source).ok()?;
+    meta.file_type().is_block_device().then(|| meta.rdev())
+}
+
+// what files are sorted on to place possible duplicates next to each other
+fn sort_key(file: &FileInfo) -> (i64, &[u8], &[u8]) {
+    (file.stat_size, &file.somebytes, &file.digest)
+}
+
+fn same_size_and_buffer(a: &FileInfo, b: &FileInfo) -> bool {
+    sort_key(a) == sort_key(b)
+}
+
+/// Makes a size in bytes into a more readable form like "45 B" or
+/// "3 GiB", with 1024 as base.
+pub fn format_size(mut size: i64) -> String {
+    let mut range = 0;
+    let mut tmp = 0;
+    while size > 1024 {
+        tmp = size >> 9;
+        size = tmp >> 1;
+        range += 1;
+    }
+    // round up if necessary
+    if tmp & 0x1 != 0 {
+        size += 1;
     }
+    // source of capitalization rules etc:
+    // https://en.wikipedia.org/wiki/Binary_prefix
+    let prefix = match range {
+        0 => "B",
+        1 => "KiB",
+        2 => "MiB",
+        3 => "GiB",
+        4 => "TiB",
+        5 => "PiB",
+        6 => "EiB",
+        _ => "!way too much!",
+    };
+    format!("{} {}", size, prefix)
 }
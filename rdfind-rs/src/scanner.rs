@@ -0,0 +1,419 @@
+// The staged duplicate search from orig_src/rdfind.cc, as a library API.
+// See LICENSE for further details.
+
+use crate::checksum::ChecksumType;
+use crate::checksum_cache::ChecksumCache;
+use crate::dirlist::Dirlist;
+use crate::error::{Error, ErrorReport};
+use crate::file_reader::ReadBackend;
+use crate::fileinfo::{DupType, FileInfo, ReadToBufferMode};
+use crate::rdutil::{Rdutil, ReadOptions};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Progress reported by `Scanner::scan_with_progress` as the stages run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress<'a> {
+    /// About to look for files below `root`.
+    Scanning { root: &'a Path },
+    /// Found `nfiles` files below `root`.
+    Scanned { root: &'a Path, nfiles: usize },
+    /// All roots are scanned, `nfiles` files were found in total.
+    Collected { nfiles: usize },
+    /// Files sharing device and inode with a higher ranked file were dropped.
+    RemovedIdenticalInodes { removed: usize },
+    /// The candidates add up to `bytes` bytes.
+    TotalSize { bytes: i64 },
+    /// Files with a size no other file has were dropped.
+    RemovedUniqueSizes { removed: usize, left: usize },
+    /// About to compare the candidates on `description`, like "first bytes".
+    Eliminating { description: &'a str },
+    /// The comparison dropped `removed` files, `left` are left.
+    Eliminated { removed: usize, left: usize },
+}
+
+/// Finds duplicate files below a set of roots.
+///
+/// Roots given earlier rank higher: when a file is found in several places,
+/// the copy from the earliest root is the original and the others are its
+/// duplicates. See RANKING in the man page for the details.
+///
+/// ```no_run
+/// use rdfind_rs::scanner::Scanner;
+///
+/// let duplicates = Scanner::new().root("/home").root("/backup").scan();
+/// for group in duplicates.groups() {
+///     println!("{} has {} copies", group.original.filename.display(), group.duplicates.len());
+/// }
+/// ```
+pub struct Scanner {
+    roots: Vec<(PathBuf, i32)>,
+    minimumfilesize: i64,
+    maximumfilesize: i64,
+    followsymlinks: bool,
+    remove_identical_inode: bool,
+    deterministic: bool,
+    checksums: Vec<ChecksumType>,
+    firstbytes: usize,
+    lastbytes: usize,
+    buffersize: usize,
+    threads: usize,
+    sleep: Duration,
+    cache: Option<ChecksumCache>,
+    progressive: bool,
+    backend: ReadBackend,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Scanner {
+            roots: Vec::new(),
+            minimumfilesize: 1,
+            maximumfilesize: i64::MAX,
+            followsymlinks: false,
+            remove_identical_inode: true,
+            deterministic: true,
+            checksums: Vec::new(),
+            firstbytes: 64,
+            lastbytes: 64,
+            buffersize: 1 << 20,
+            threads: 1,
+            sleep: Duration::ZERO,
+            cache: None,
+            progressive: false,
+            backend: ReadBackend::Buffered,
+        }
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file or directory to search, ranked below the ones added
+    /// before it.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        let priority = self.roots.last().map_or(1, |&(_, priority)| priority + 1);
+        self.roots.push((root.into(), priority));
+        self
+    }
+
+    /// Adds a file or directory to search with an explicit priority, where
+    /// lower ranks higher. The priority is written to the results file.
+    pub fn root_with_priority(mut self, root: impl Into<PathBuf>, priority: i32) -> Self {
+        self.roots.push((root.into(), priority));
+        self
+    }
+
+    /// Ignores files smaller than `size` bytes. The default of 1 ignores
+    /// empty files.
+    pub fn min_size(mut self, size: i64) -> Self {
+        self.minimumfilesize = size;
+        self
+    }
+
+    /// Ignores files of `size` bytes and larger. Zero disables the check,
+    /// which is the default.
+    pub fn max_size(mut self, size: i64) -> Self {
+        self.maximumfilesize = if size == 0 { i64::MAX } else { size };
+        self
+    }
+
+    pub fn follow_symlinks(mut self, followsymlinks: bool) -> Self {
+        self.followsymlinks = followsymlinks;
+        self
+    }
+
+    /// Whether to only keep the highest ranked of files sharing device and
+    /// inode, which is the default. They are the same file, so can not be
+    /// deduplicated.
+    pub fn remove_identical_inodes(mut self, remove: bool) -> Self {
+        self.remove_identical_inode = remove;
+        self
+    }
+
+    /// Whether to make results independent of the order the file system
+    /// lists directories in, which is the default.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Adds a checksum to compare candidates on, after the first and last
+    /// bytes. Checksums are applied in the order given, except crc32c which
+    /// is cheap and goes first, to leave less for the others to read.
+    /// Without any, sha1 is used. So is it after crc32c if that is the
+    /// only one, since 32 bits are too few to tell duplicates by.
+    pub fn checksum(mut self, checksumtype: ChecksumType) -> Self {
+        self.checksums.push(checksumtype);
+        self
+    }
+
+    /// How many bytes from the start of each candidate to compare before
+    /// the last bytes and the checksums. The default is 64, zero skips the
+    /// step.
+    pub fn first_bytes(mut self, firstbytes: usize) -> Self {
+        self.firstbytes = firstbytes;
+        self
+    }
+
+    /// How many bytes from the end of each candidate to compare before the
+    /// checksums. The default is 64, zero skips the step.
+    pub fn last_bytes(mut self, lastbytes: usize) -> Self {
+        self.lastbytes = lastbytes;
+        self
+    }
+
+    /// The chunk size, in bytes, to use when reading files.
+    pub fn buffer_size(mut self, buffersize: usize) -> Self {
+        self.buffersize = buffersize;
+        self
+    }
+
+    /// How many threads to read files with. The default is 1. Files on a
+    /// rotating disk are read by one thread at a time regardless.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// How long to sleep between reading files, to go easy on the disk.
+    pub fn sleep(mut self, sleep: Duration) -> Self {
+        self.sleep = sleep;
+        self
+    }
+
+    /// Takes checksums of unchanged files from `cache` instead of reading
+    /// the files. The cache is saved with the new checksums after each
+    /// search.
+    pub fn checksum_cache(mut self, cache: ChecksumCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Whether to compute checksums a chunk at a time for all candidates
+    /// of a size together, dropping files as soon as they differ from the
+    /// others. Saves reading most of large files that only share their
+    /// first and last bytes. The default is to checksum one whole file at
+    /// a time.
+    pub fn progressive(mut self, progressive: bool) -> Self {
+        self.progressive = progressive;
+        self
+    }
+
+    /// How to read files. The default reads through the page cache.
+    pub fn read_backend(mut self, backend: ReadBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Runs the search.
+    pub fn scan(&self) -> Duplicates {
+        self.scan_with_progress(|_| {})
+    }
+
+    /// Runs the search, calling `progress` as each stage starts and ends.
+    pub fn scan_with_progress<F: FnMut(Progress)>(&self, mut progress: F) -> Duplicates {
+        // this vector holds the information about all files found
+        let mut filelist: Vec<FileInfo> = Vec::new();
+
+        // an object to do sorting and duplicate finding
+        let mut gswd = Rdutil::new(&mut filelist);
+
+        // an object to traverse the directory structure
+        let dirlist = Dirlist::new(self.followsymlinks);
+
+        // the files which could not be looked at
+        let mut errors = ErrorReport::new();
+
+        for (root, priority) in &self.roots {
+            let lastsize = gswd.list.len();
+            progress(Progress::Scanning { root });
+            let mut stat_errors = ErrorReport::new();
+            dirlist.walk(
+                root,
+                *priority,
+                &mut |mut tmp| {
+                    rddebug!("report({})", tmp.filename.display());
+                    if let Err(e) = tmp.read_file_info() {
+                        stat_errors.push(e);
+                    } else if tmp.is_file
+                        && tmp.stat_size >= self.minimumfilesize
+                        && tmp.stat_size < self.maximumfilesize
+                    {
+                        gswd.list.push(tmp);
+                    }
+                },
+                &mut errors,
+            );
+            errors.append(&mut stat_errors);
+            progress(Progress::Scanned {
+                root,
+                nfiles: gswd.list.len() - lastsize,
+            });
+
+            // if we want deterministic output, we will sort the newly added
+            // items on depth, then filename.
+            if self.deterministic {
+                gswd.sort_on_depth_and_name(lastsize);
+            }
+        }
+        progress(Progress::Collected {
+            nfiles: gswd.list.len(),
+        });
+
+        // mark files with a number for correct ranking. The only ordering at
+        // this point is that files found on early roots are earlier in the
+        // list.
+        gswd.mark_items();
+
+        if self.remove_identical_inode {
+            // remove files with identical devices and inodes from the list
+            let removed = gswd.remove_identical_inodes();
+            progress(Progress::RemovedIdenticalInodes { removed });
+        }
+
+        progress(Progress::TotalSize {
+            bytes: gswd.total_size_in_bytes(),
+        });
+
+        let removed = gswd.remove_unique_sizes();
+        progress(Progress::RemovedUniqueSizes {
+            removed,
+            left: gswd.list.len(),
+        });
+
+        // ok. we now need to do something stronger to disambiguate the
+        // duplicate candidates. start looking at the contents.
+        let mut modes = vec![(ReadToBufferMode::NotDefined, String::new())];
+        if self.firstbytes > 0 {
+            modes.push((
+                ReadToBufferMode::ReadFirstBytes(self.firstbytes),
+                String::from("first bytes"),
+            ));
+        }
+        if self.lastbytes > 0 {
+            modes.push((
+                ReadToBufferMode::ReadLastBytes(self.lastbytes),
+                String::from("last bytes"),
+            ));
+        }
+        let mut checksums = self.checksums.clone();
+        checksums.sort_by_key(|&checksumtype| checksumtype != ChecksumType::CRC32C);
+        if checksums
+            .last()
+            .is_none_or(|&checksumtype| checksumtype == ChecksumType::CRC32C)
+        {
+            checksums.push(ChecksumType::SHA1);
+        }
+        for checksumtype in checksums {
+            let description = if self.progressive {
+                format!("{} checksum, chunk by chunk", checksumtype.name())
+            } else {
+                format!("{} checksum", checksumtype.name())
+            };
+            modes.push((checksumtype.into(), description));
+        }
+
+        let options = ReadOptions {
+            buffersize: self.buffersize,
+            sleep: self.sleep,
+            threads: self.threads,
+            cache: self.cache.as_ref(),
+            backend: self.backend,
+        };
+        for pair in modes.windows(2) {
+            let (lasttype, _) = pair[0];
+            let (filltype, description) = &pair[1];
+            progress(Progress::Eliminating { description });
+
+            let unreadable = match filltype.checksum_type() {
+                Some(checksumtype) if self.progressive => {
+                    gswd.compare_progressively(checksumtype, &options, &mut errors)
+                }
+                // read bytes (destroys the sorting, for disk reading
+                // efficiency)
+                _ => gswd.fill_with_bytes(*filltype, lasttype, &options, &mut errors),
+            };
+
+            // remove non-duplicates
+            let removed = unreadable + gswd.remove_uniq_size_and_buffer();
+            progress(Progress::Eliminated {
+                removed,
+                left: gswd.list.len(),
+            });
+        }
+
+        // What is left now is a list of duplicates, ordered on size and
+        // bytes, with all unique files gone. Go ahead and mark them.
+        gswd.mark_duplicates();
+        // duplicates which are reflinks of their original take no space
+        gswd.mark_shared_extents();
+
+        if let Some(cache) = &self.cache
+            && let Err(source) = cache.save()
+        {
+            errors.push(Error::SaveCache {
+                path: cache.path().to_path_buf(),
+                source,
+            });
+        }
+
+        Duplicates {
+            files: filelist,
+            errors,
+        }
+    }
+}
+
+/// The duplicates found by a `Scanner`.
+pub struct Duplicates {
+    files: Vec<FileInfo>,
+    errors: ErrorReport,
+}
+
+/// A file and the duplicates of it.
+#[derive(Clone, Copy)]
+pub struct DuplicateGroup<'a> {
+    pub original: &'a FileInfo,
+    pub duplicates: &'a [FileInfo],
+}
+
+impl Duplicates {
+    /// Each original followed by its duplicates, as written to the results
+    /// file.
+    pub fn files(&self) -> &[FileInfo] {
+        &self.files
+    }
+
+    pub fn into_files(self) -> Vec<FileInfo> {
+        self.files
+    }
+
+    /// The files and directories that could not be looked at. They were
+    /// left out of the search.
+    pub fn errors(&self) -> &ErrorReport {
+        &self.errors
+    }
+
+    /// Takes the errors, to add the ones from acting on the duplicates to.
+    pub fn take_errors(&mut self) -> ErrorReport {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Iterates over the groups of identical files.
+    pub fn groups(&self) -> impl Iterator<Item = DuplicateGroup<'_>> {
+        self.files
+            .chunk_by(|_, b| b.duptype != DupType::FirstOccurrence)
+            .map(|group| DuplicateGroup {
+                original: &group[0],
+                duplicates: &group[1..],
+            })
+    }
+
+    /// Gives access to the results file and the delete and link actions.
+    pub fn rdutil(&mut self) -> Rdutil<'_> {
+        Rdutil::new(&mut self.files)
+    }
+}
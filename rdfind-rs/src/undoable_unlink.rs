@@ -6,14 +6,13 @@
 // This file was ported from the C++ UndoableUnlink class.
 
 use crate::easy_random::EasyRandom;
+use crate::error::{Error, Result};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, PartialEq)]
 enum State {
-    Uninitialized,
-    FailedMoveToTemporary,
     MovedToTemporary,
     FailedUndo,
     Undone,
@@ -29,28 +28,25 @@ pub struct UndoableUnlink {
 
 impl UndoableUnlink {
     /// Moves the file to a random temporary name in the same directory.
-    pub fn new(filename: &str) -> Self {
-        let filename = PathBuf::from(filename);
-        let mut tempfilename;
+    pub fn new(filename: &Path) -> Result<Self> {
+        let filename = filename.to_path_buf();
         let easy_random = EasyRandom::new();
         let rand_name = easy_random.make_random_file_string(12);
-        if let Some(parent) = filename.parent() {
-            tempfilename = parent.join(rand_name);
-        } else {
-            tempfilename = PathBuf::from(rand_name);
-        }
-        let state = match fs::rename(&filename, &tempfilename) {
-            Ok(_) => State::MovedToTemporary,
-            Err(e) => {
-                eprintln!("Failed moving {:?} to a temporary file: {}", filename, e);
-                State::FailedMoveToTemporary
-            }
+        let tempfilename = match filename.parent() {
+            Some(parent) => parent.join(rand_name),
+            None => PathBuf::from(rand_name),
         };
-        UndoableUnlink {
-            state,
+        if let Err(source) = fs::rename(&filename, &tempfilename) {
+            return Err(Error::MoveToTemporary {
+                path: filename,
+                source,
+            });
+        }
+        Ok(UndoableUnlink {
+            state: State::MovedToTemporary,
             filename,
             tempfilename,
-        }
+        })
     }
 
     /// Checks if file is moved and ready for undo or unlink
@@ -59,12 +55,11 @@ impl UndoableUnlink {
     }
 
     /// Moves the file back from the random name into the original filename
-    pub fn undo(&mut self) -> io::Result<()> {
+    pub fn undo(&mut self) -> Result<()> {
         if self.state != State::MovedToTemporary {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
+            return Err(self.undo_error(io::Error::other(
                 "api misuse - calling undo() now is a programming error",
-            ));
+            )));
         }
         match fs::rename(&self.tempfilename, &self.filename) {
             Ok(_) => {
@@ -73,22 +68,17 @@ impl UndoableUnlink {
             }
             Err(e) => {
                 self.state = State::FailedUndo;
-                eprintln!(
-                    "Failed moving file from temporary back to {:?}: {}",
-                    self.filename, e
-                );
-                Err(e)
+                Err(self.undo_error(e))
             }
         }
     }
 
     /// Removes the moved file
-    pub fn unlink(&mut self) -> io::Result<()> {
+    pub fn unlink(&mut self) -> Result<()> {
         if self.state != State::MovedToTemporary {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
+            return Err(self.unlink_error(io::Error::other(
                 "api misuse - calling unlink() now is a programming error",
-            ));
+            )));
         }
         match fs::remove_file(&self.tempfilename) {
             Ok(_) => {
@@ -97,20 +87,36 @@ impl UndoableUnlink {
             }
             Err(e) => {
                 self.state = State::FailedUnlink;
-                eprintln!(
-                    "Failed unlinking temporary file made from {:?}: {}",
-                    self.filename, e
-                );
-                Err(e)
+                Err(self.unlink_error(e))
             }
         }
     }
+
+    fn undo_error(&self, source: io::Error) -> Error {
+        Error::Undo {
+            path: self.filename.clone(),
+            temporary: self.tempfilename.clone(),
+            source,
+        }
+    }
+
+    fn unlink_error(&self, source: io::Error) -> Error {
+        Error::Unlink {
+            path: self.filename.clone(),
+            temporary: self.tempfilename.clone(),
+            source,
+        }
+    }
 }
 
 impl Drop for UndoableUnlink {
     fn drop(&mut self) {
-        if self.state == State::MovedToTemporary {
-            let _ = self.undo();
+        // there is no one to return an error to here. callers wanting to
+        // know if the file made it back call undo() themselves.
+        if self.state == State::MovedToTemporary
+            && let Err(e) = self.undo()
+        {
+            eprintln!("{}", e);
         }
     }
 }
@@ -0,0 +1,42 @@
+#!/bin/sh
+# Ensures files of equal size, with equal first and last bytes, are only
+# reported as duplicates when their checksums agree.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+# writes 64 bytes of a, a middle byte given as $2 and 64 bytes of z to $1
+mkfile() {
+  {
+    head -c64 /dev/zero | tr '\0' a
+    printf '%s' "$2"
+    head -c64 /dev/zero | tr '\0' z
+  } >"$1"
+}
+
+for checksumtype in $allchecksumtypes; do
+  reset_teststate
+  mkfile a 1
+  mkfile b 2
+  $rdfind -checksum "$checksumtype" a b >rdfind.out
+  verify grep -q "^It seems like you have 0 files that are not unique$" rdfind.out
+  dbgecho "$checksumtype kept different files of equal size apart"
+
+  mkfile c 1
+  $rdfind -checksum "$checksumtype" a b c >rdfind.out
+  verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+  dbgecho "$checksumtype found the real duplicate"
+done
+
+# crc32c is too weak to be the last word, sha1 is used after it
+reset_teststate
+mkfile a 1
+mkfile c 1
+$rdfind -checksum crc32c a c >rdfind.out
+verify grep -q "^Now eliminating candidates based on crc32c checksum: " rdfind.out
+verify grep -q "^Now eliminating candidates based on sha1 checksum: " rdfind.out
+$rdfind -checksum crc32c -checksum sha256 a c >rdfind.out
+verify [ "$(grep -c "based on sha1 checksum" rdfind.out)" -eq 0 ]
+dbgecho "passed crc32c followed by sha1"
+
+dbgecho "all is good in this test!"
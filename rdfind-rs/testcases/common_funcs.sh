@@ -0,0 +1,70 @@
+#!/bin/sh
+# common functionality for the unit tests, adapted from
+# orig_src/testcases/common_funcs.sh to run against the rust binary.
+# build first with "cargo build", or point RDFIND at the binary to test.
+
+#bail out on the first error
+set -e
+
+me="$(basename "$0")"
+
+export allchecksumtypes="md5 sha1 sha256 sha512 xxh128 blake3 crc32c"
+
+#where is the test scripts dir?
+testscriptsdir="$(dirname "$(readlink -f "$0")")"
+export testscriptsdir
+
+# shellcheck disable=SC3037
+/bin/echo -n "$me: checking for rdfind ..."
+rdfind=${RDFIND:-$testscriptsdir/../target/debug/rdfind-rs}
+if [ ! -x "$rdfind" ]; then
+  echo "could not find $rdfind"
+  exit 1
+fi
+echo " OK."
+
+dbgecho() {
+  echo "$0 debug: " "$@"
+}
+
+#create a temporary directory, which is automatically deleted
+#on exit
+datadir=$(mktemp -d -t rdfindtestcases.d.XXXXXXXXXXXX)
+dbgecho "temp dir is $datadir"
+
+cleanup() {
+  cd /
+  rm -rf "$datadir"
+}
+
+if [ -z "$KEEPTEMPDIR" ]; then
+  trap cleanup INT QUIT EXIT
+fi
+
+[ -d "$datadir" ]
+cd "$datadir"
+
+reset_teststate() {
+  cd /
+  rm -rf "$datadir"
+  mkdir -p "$datadir"
+  cd "$datadir"
+}
+
+verify() {
+  if ! "$@"; then
+    echo "failed asserting $*"
+    exit 1
+  fi
+}
+
+# changes the byte of file $1 at offset $2 to a different value
+patchbyte() {
+  old=$(dd if="$1" bs=1 skip="$2" count=1 2>/dev/null)
+  if [ "$old" = X ]; then
+    new=Y
+  else
+    new=X
+  fi
+  printf '%s' "$new" | dd of="$1" bs=1 seek="$2" conv=notrunc 2>/dev/null
+}
@@ -0,0 +1,70 @@
+#!/bin/sh
+# Ensures apply leaves files alone when the results file was edited or the
+# files changed in place since the scan, instead of linking or deleting
+# files which are not duplicates, and never panics on such input.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+makefiles() {
+  mkdir -p d
+  head -c5000 /dev/urandom >d/a
+  cp d/a d/b
+  head -c6000 /dev/urandom >d/c
+  cp d/c d/e
+  $rdfind d >rdfind.out
+}
+
+# runs apply with the arguments, expecting it to skip files
+apply_skips() {
+  status=0
+  $rdfind apply "$@" >apply.out 2>apply.err || status=$?
+  verify [ "$status" -eq 2 ]
+}
+
+# an original removed from the results file does not make its duplicates
+# belong to the original listed before them
+reset_teststate
+makefiles
+grep -v "^DUPTYPE_FIRST_OCCURRENCE .* d/c$" results.txt >edited.txt
+inode=$(stat -c %i d/e)
+apply_skips -makehardlinks true edited.txt
+verify [ "$(stat -c %i d/e)" = "$inode" ]
+verify [ "$(stat -c %i d/a)" != "$(stat -c %i d/b)" ]
+verify cmp d/c d/e
+verify grep -q "^\"d/e\" is not a duplicate of \"d/a\" listed before it" apply.err
+dbgecho "passed removed original test"
+
+# a duplicate which changed in place, keeping its size, is not deleted
+reset_teststate
+makefiles
+patchbyte d/b 100
+apply_skips -deleteduplicates true results.txt
+verify [ -e d/b ]
+verify [ ! -e d/e ]
+verify grep -q "^\"d/b\" differs from \"d/a\", leaving it alone" apply.err
+dbgecho "passed changed in place test"
+
+# duplicates listed before any original
+reset_teststate
+makefiles
+grep -v "^DUPTYPE_FIRST_OCCURRENCE" results.txt >edited.txt
+apply_skips -deleteduplicates true edited.txt
+verify [ -e d/b ]
+verify [ -e d/e ]
+verify grep -q "^\"d/b\" has no original listed before it" apply.err
+dbgecho "passed missing originals test"
+
+# a file of unknown type is refused
+reset_teststate
+makefiles
+sed "s/^DUPTYPE_WITHIN_SAME_TREE/DUPTYPE_UNKNOWN/" results.txt >edited.txt
+status=0
+$rdfind apply -deleteduplicates true edited.txt >apply.out 2>apply.err || status=$?
+verify [ "$status" -eq 1 ]
+verify [ -e d/b ]
+verify [ -e d/e ]
+verify grep -q "a file of unknown duplicate type" apply.err
+dbgecho "passed unknown duplicate type test"
+
+dbgecho "all is good in this test!"
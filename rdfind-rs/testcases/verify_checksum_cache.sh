@@ -0,0 +1,83 @@
+#!/bin/sh
+# Ensures the checksum cache is used for unchanged files, and not for files
+# which changed since their checksum was cached.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+reset_teststate
+XDG_CACHE_HOME="$datadir/cache"
+export XDG_CACHE_HOME
+cachefile="$XDG_CACHE_HOME/rdfind/checksums"
+mkdir dir
+head -c1000 /dev/urandom >dir/a
+cp dir/a dir/b
+
+$rdfind -cache true dir >rdfind.out
+verify [ -f "$cachefile" ]
+verify [ "$(grep -c "^sha1 " "$cachefile")" -eq 2 ]
+verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+dbgecho "passed filling the cache"
+
+# makes the cached sha1 of the file with inode $1 wrong
+zero_digest() {
+  sed -i "s/^\(sha1 [0-9]* $1 [0-9]* [0-9]* [0-9]*\) [0-9a-f]*/\1 $(printf '%040d' 0)/" "$cachefile"
+}
+
+# make the cached checksum of b wrong. finding no duplicates shows the cache
+# was used instead of reading the file.
+inode=$(stat -c %i dir/b)
+zero_digest "$inode"
+$rdfind -cache true dir >rdfind.out
+verify grep -q "^It seems like you have 0 files that are not unique$" rdfind.out
+dbgecho "passed using the cache"
+
+$rdfind -cache false dir >rdfind.out
+verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+dbgecho "passed bypassing the cache"
+
+# a new modification time makes the cached checksum stale
+touch -d "2000-01-01" dir/b
+$rdfind -cache true dir >rdfind.out
+verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+dbgecho "passed invalidating the cache"
+
+zero_digest "$inode"
+$rdfind -cache clear dir >rdfind.out
+verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+verify [ "$(grep -c "^sha1 " "$cachefile")" -eq 2 ]
+dbgecho "passed clearing the cache"
+
+# entries not used for a long time are dropped, lines which are not
+# understood, like those of a checksum registered by another program, are
+# kept
+today=$(($(date +%s) / 86400))
+echo "sha1 1 2 3 4 5 $(printf '%040d' 0) $((today - 100))" >>"$cachefile"
+echo "myhash 1 2 3 4 5 abcd $today" >>"$cachefile"
+echo "myhash 1 3 3 4 5 abcd $((today - 100))" >>"$cachefile"
+echo "something else" >>"$cachefile"
+$rdfind -cache true dir >rdfind.out
+verify [ "$(grep -c "^sha1 " "$cachefile")" -eq 2 ]
+verify grep -q "^myhash 1 2 3 4 5 abcd $today$" "$cachefile"
+verify [ "$(grep -c "^myhash 1 3 " "$cachefile")" -eq 0 ]
+verify grep -q "^something else$" "$cachefile"
+verify grep -q "^sha1 [0-9]* $inode [0-9 ]*[0-9a-f]* $today$" "$cachefile"
+dbgecho "passed aging the cache"
+
+# a run which changes nothing leaves the cache file alone
+touch -d "2000-01-01" "$cachefile"
+$rdfind -cache true dir >rdfind.out
+verify [ "$(stat -c %Y "$cachefile")" = "$(date -d "2000-01-01" +%s)" ]
+dbgecho "passed not rewriting an unchanged cache"
+
+# a cache of another version is refused, until it is cleared
+sed -i "1s/.*/# rdfind checksum cache 0/" "$cachefile"
+status=0
+$rdfind -cache true dir >rdfind.out 2>rdfind.err || status=$?
+verify [ "$status" -eq 1 ]
+verify grep -q "^could not open cache \"$cachefile\": not a checksum cache of this version of rdfind$" rdfind.err
+$rdfind -cache clear dir >rdfind.out
+verify [ "$(head -n1 "$cachefile")" = "# rdfind checksum cache 1" ]
+dbgecho "passed checking the version"
+
+dbgecho "all is good in this test!"
@@ -0,0 +1,33 @@
+#!/bin/sh
+# Ensures checksums joined with + only find files matching on all of them,
+# using the md5 collision files from the upstream test suite.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+collisions="$testscriptsdir/../../orig_src/testcases/md5collisions"
+
+reset_teststate
+mkdir md5coll
+cp "$collisions/"*.ps md5coll
+
+$rdfind -checksum md5 -dryrun true md5coll >rdfind.out
+verify grep -q "^(DRYRUN MODE) It seems like you have 2 files that are not unique$" rdfind.out
+for progressive in false true; do
+  $rdfind -checksum md5+sha1 -progressive $progressive -dryrun true md5coll >rdfind.out
+  verify grep -q "^(DRYRUN MODE) It seems like you have 0 files that are not unique$" rdfind.out
+done
+dbgecho "passed telling the collision apart"
+
+reset_teststate
+XDG_CACHE_HOME="$datadir/cache"
+export XDG_CACHE_HOME
+mkdir dir
+head -c100000 /dev/urandom >dir/a
+cp dir/a dir/b
+$rdfind -checksum xxh128+sha256 -cache true dir >rdfind.out
+verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+verify [ "$(grep -c "^xxh128+sha256 " "$XDG_CACHE_HOME/rdfind/checksums")" -eq 2 ]
+dbgecho "passed finding and caching duplicates"
+
+dbgecho "all is good in this test!"
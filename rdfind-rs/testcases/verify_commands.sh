@@ -0,0 +1,56 @@
+#!/bin/sh
+# Ensures the GNU style long options and the scan, report and apply
+# commands work, next to the original single dash options.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+makefiles() {
+  mkdir -p dir
+  head -c1000 /dev/urandom >dir/a
+  cp dir/a dir/b
+  cp dir/a dir/c
+}
+
+reset_teststate
+makefiles
+$rdfind --delete-duplicates --checksum=sha256 --min-size 1K dir >rdfind.out
+verify [ -e dir/a ]
+verify [ -e dir/b ]
+dbgecho "passed min size with suffix test"
+
+reset_teststate
+makefiles
+$rdfind --delete-duplicates=true --checksum=sha256 --max-size=1K dir >rdfind.out
+verify [ -e dir/a ]
+verify [ ! -e dir/b ]
+verify [ ! -e dir/c ]
+dbgecho "passed long option test"
+
+reset_teststate
+makefiles
+$rdfind scan -deleteduplicates false dir >rdfind.out
+verify [ -e dir/b ]
+$rdfind report results.txt >report.out
+verify grep -q "^dir/a$" report.out
+verify grep -q "^  dir/b$" report.out
+verify grep -q "^It seems like you have 3 files that are not unique$" report.out
+dbgecho "passed scan and report test"
+
+echo changed >>dir/c
+status=0
+$rdfind apply --make-hardlinks results.txt >apply.out 2>apply.err || status=$?
+verify [ "$status" -eq 2 ]
+verify [ "$(stat -c %i dir/a)" = "$(stat -c %i dir/b)" ]
+verify [ "$(stat -c %i dir/a)" != "$(stat -c %i dir/c)" ]
+verify grep -q "^Removed 1 files that changed since the results file was made.$" apply.out
+verify grep -q "^\"dir/c\" changed since the results file was made" apply.err
+dbgecho "passed apply test"
+
+if $rdfind apply results.txt >apply.out 2>&1; then
+  dbgecho "apply without an action should have failed"
+  exit 1
+fi
+dbgecho "passed apply without action test"
+
+dbgecho "all is good in this test!"
@@ -0,0 +1,35 @@
+#!/bin/sh
+# Ensures -dedupeextents shares the data of duplicates in place, keeping
+# their inode, or leaves them alone with a clear message on file systems
+# which can not share extents.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+reset_teststate
+mkdir dir
+head -c100000 /dev/urandom >dir/a
+cp dir/a dir/b
+inode=$(stat -c %i dir/b)
+
+$rdfind -dedupeextents true -dryrun true dir >rdfind.out
+verify grep -q "^(DRYRUN MODE) dedupe extents of dir/b with dir/a$" rdfind.out
+verify grep -q "^(DRYRUN MODE) Deduplicating 1 files.$" rdfind.out
+dbgecho "passed dry run"
+
+status=0
+$rdfind -dedupe-extents true dir >rdfind.out 2>rdfind.err || status=$?
+if [ "$status" -eq 0 ]; then
+  verify grep -q "^Deduplicated .* in 1 files.$" rdfind.out
+  dbgecho "passed deduplicating"
+else
+  verify [ "$status" -eq 2 ]
+  verify grep -q "^Deduplicated 0 B in 0 files.$" rdfind.out
+  verify grep -q "does not support reflinks" rdfind.err
+  dbgecho "passed refusing on a file system without shared extents"
+fi
+# either way, b is the same file with the same contents
+verify [ "$(stat -c %i dir/b)" = "$inode" ]
+verify cmp dir/a dir/b
+
+dbgecho "all is good in this test!"
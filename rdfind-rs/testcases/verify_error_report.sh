@@ -0,0 +1,76 @@
+#!/bin/sh
+# Ensures files that can not be deleted, directories nested too deep and a
+# results file which can not be written are reported at the end of the run,
+# with the reason, and make rdfind exit with status 2.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+reset_teststate
+mkdir -p readonly.d
+echo xxx >readonly.d/a
+echo xxx >readonly.d/b
+chmod 500 readonly.d
+
+if [ "$(id -u)" -eq 0 ]; then
+  # if running as root, directory rights are not respected. drop the capability
+  # for doing that (requires capsh from package libcap2-bin)
+  MAYBEDROP="capsh --drop=CAP_DAC_OVERRIDE -- -c"
+else
+  MAYBEDROP="/bin/sh -c"
+fi
+status=0
+$MAYBEDROP "$rdfind -deleteduplicates true readonly.d >rdfind.out 2>rdfind.err" || status=$?
+chmod 700 readonly.d
+verify [ "$status" -eq 2 ]
+verify [ -e readonly.d/a ]
+verify [ -e readonly.d/b ]
+verify grep -q "^1 files were skipped:$" rdfind.err
+verify grep -q "^failed deleting file \"readonly.d/b\": Permission denied" rdfind.err
+dbgecho "passed permission denied test"
+
+reset_teststate
+echo xxx >a
+echo xxx >b
+status=0
+$rdfind -deleteduplicates true a b vanished >rdfind.out 2>rdfind.err || status=$?
+verify [ "$status" -eq 2 ]
+verify [ -e a ]
+verify [ ! -e b ]
+verify grep -q "^failed reading \"vanished\": No such file or directory" rdfind.err
+dbgecho "passed missing file test"
+
+reset_teststate
+echo xxx >a
+echo xxx >b
+status=0
+$rdfind -outputname nodir/results.txt a b >rdfind.out 2>rdfind.err || status=$?
+verify [ "$status" -eq 2 ]
+verify grep -q "^failed writing results file \"nodir/results.txt\": No such file or directory" rdfind.err
+dbgecho "passed results file test"
+
+reset_teststate
+deep=tree
+i=0
+while [ $i -lt 55 ]; do
+  deep="$deep/d"
+  i=$((i + 1))
+done
+mkdir -p "$deep"
+echo xxx >tree/a
+echo xxx >"$deep/b"
+status=0
+$rdfind tree >rdfind.out 2>rdfind.err || status=$?
+verify [ "$status" -eq 2 ]
+verify grep -q "^1 files were skipped:$" rdfind.err
+verify grep -q "^recursion limit exceeded at \"tree\(/d\)\{50\}\", skipping it$" rdfind.err
+dbgecho "passed recursion limit test"
+
+reset_teststate
+echo xxx >a
+echo xxx >b
+$rdfind -deleteduplicates true a b >rdfind.out 2>rdfind.err
+verify [ ! -s rdfind.err ]
+dbgecho "passed no errors test"
+
+dbgecho "all is good in this test!"
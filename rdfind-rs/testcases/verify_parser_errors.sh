@@ -0,0 +1,86 @@
+#!/bin/sh
+# Ensures bad command lines are rejected with the same messages as the
+# C++ rdfind, and that arguments need not be valid UTF-8.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+# runs rdfind with the given arguments, which must fail with the message
+# stored in $expected.
+expect_failure() {
+  if $rdfind "$@" >rdfind.out 2>rdfind.err; then
+    echo "rdfind $* should have failed"
+    exit 1
+  fi
+  if [ "$(cat rdfind.err)" != "$expected" ]; then
+    echo "rdfind $* gave the message \"$(cat rdfind.err)\", expected \"$expected\""
+    exit 1
+  fi
+}
+
+reset_teststate
+mkdir dir
+
+expected="expected true or false after -dryrun, not end of argument list."
+expect_failure -dryrun
+expected="expected true or false after -makehardlinks, not \"maybe\""
+expect_failure -makehardlinks maybe dir
+expected="expected true or false after -n, not \"dir\""
+expect_failure -n dir
+expected="expected string after -outputname, not end of argument list."
+expect_failure -outputname
+expected="expected string after -checksum, not end of argument list."
+expect_failure -checksum
+# the out of bounds error is a programming error and can not be provoked
+# from the command line. tests/cmdline_parser.rs covers it through
+# Parser::from_args.
+dbgecho "passed parser error tests"
+
+expected="bad argument 3"
+expect_failure -n true "" dir
+expected="did not understand option 1:\"-nosuchoption\""
+expect_failure -nosuchoption dir
+expected="negative value of minsize not allowed"
+expect_failure -minsize -1 dir
+expected="negative value of maxsize not allowed"
+expect_failure -maxsize -1 dir
+expected="expected a number after -minsize, not \"many\""
+expect_failure -minsize many dir
+expected="maximum filesize 3 must be larger than minimum filesize 3"
+expect_failure -minsize 3 -maxsize 3 dir
+expected="expected md5/sha1/sha256/sha512/xxh128/blake3/crc32c, not \"crc\""
+expect_failure -checksum crc dir
+expected="expected md5/sha1/sha256/sha512/xxh128/blake3/crc32c, not \"md5+crc\""
+expect_failure -checksum md5+crc dir
+expected="negative value of firstbytes not allowed"
+expect_failure -firstbytes -1 dir
+expected="a maximum of 128 MiB firstbytes is allowed, got 1048576 MiB"
+expect_failure -firstbytes 1T dir
+expected="a maximum of 128 MiB lastbytes is allowed, got 129 MiB"
+expect_failure -lastbytes 129M dir
+expected="expected a number after -lastbytes, not \"4X\""
+expect_failure -lastbytes 4X dir
+expected="expected buffered, mmap or direct after -readbackend, not \"odirect\""
+expect_failure -readbackend odirect dir
+expected="expected absolute or relative after -symlinkstyle, not \"relativ\""
+expect_failure -symlinkstyle relativ dir
+expected="a negative or zero buffersize is not allowed"
+expect_failure -buffersize 0 dir
+expected="a maximum of 128 MiB buffersize is allowed, got 256 MiB"
+expect_failure -buffersize 268435456 dir
+expected="sorry, can only understand a few sleep values for now. \"7ms\" is not among them."
+expect_failure -sleep 7ms dir
+dbgecho "passed option validation tests"
+
+# names that are not valid UTF-8 must be scanned and written as they are
+reset_teststate
+name=$(printf 'dir\377')
+mkdir "$name"
+head -c100 /dev/zero >"$name/a"
+head -c100 /dev/zero >"$name/b"
+$rdfind -outputname "$(printf 'results\376')" "$name" >rdfind.out
+verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+verify grep -q "$name/b\$" "$(printf 'results\376')"
+dbgecho "passed non UTF-8 argument test"
+
+dbgecho "all is good in this test!"
@@ -0,0 +1,116 @@
+#!/bin/sh
+# Property test of the path normalisation behind -makesymlinks. The
+# original and its duplicate are given through randomly spelled paths, with
+# "." and ".." components, repeated and trailing slashes, ".." after a
+# symlinked directory and names which are not valid UTF-8. Whatever the
+# spelling, each link must resolve to the original, and be the shortest
+# path to it for its style. The seed of the spellings is logged, and a
+# failing run is repeated by giving it in SEED. tests/path_normalize.rs
+# checks the normalisation itself.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+seed=${SEED:-$(od -An -N4 -tu4 /dev/urandom | tr -d ' ')}
+dbgecho "seed is $seed"
+
+# sets r to a random number from 0 to $1 - 1. it is not a function printing
+# the number, since that runs in a subshell which can not advance the seed.
+random() {
+  seed=$(((seed * 1103515245 + 12345) % 2147483648))
+  r=$((seed / 65536 % $1))
+}
+
+names="a bb $(printf 'caf\351') $(printf '\377\376')"
+
+# appends a random name from names to the variable path
+randomname() {
+  # shellcheck disable=SC2086
+  set -- $names
+  random $#
+  shift $r
+  path="$path/$1"
+}
+
+# sets out to a random spelling of the relative directory $1. every
+# directory has an empty directory sub and a symlink self pointing to
+# itself, to make detours through.
+spell() {
+  out=
+  for part in $(echo "$1" | tr / ' '); do
+    random 6
+    case $r in
+      0) out="$out./" ;;
+      1) out="${out}sub/../" ;;
+      # the kernel takes ".." from where self points, which is not what
+      # removing "self/.." from the path gives
+      2) out="$out$part/self/../" ;;
+    esac
+    out="$out$part/"
+    random 3
+    if [ $r -eq 0 ]; then
+      out="$out/"
+    fi
+  done
+  random 3
+  case $r in
+    0) ;;
+    1) out="$out." ;;
+    *) out="${out%/}" ;;
+  esac
+}
+
+makedirs() {
+  mkdir -p "$1" sub
+  ln -sfn . self
+  dir=
+  for part in $(echo "$1" | tr / ' '); do
+    dir="$dir$part/"
+    mkdir -p "${dir}sub"
+    ln -sfn . "${dir}self"
+  done
+}
+
+i=0
+while [ $i -lt 40 ]; do
+  i=$((i + 1))
+  reset_teststate
+  path=tree
+  randomname
+  randomname
+  origdir=$path
+  path=tree
+  randomname
+  randomname
+  randomname
+  dupdir=$path
+  makedirs "$origdir"
+  makedirs "$dupdir"
+  head -c1000 /dev/urandom >"$origdir/orig"
+  cp "$origdir/orig" "$dupdir/dup"
+  random 2
+  if [ $r -eq 0 ]; then
+    style=absolute
+  else
+    style=relative
+  fi
+  spell "$origdir"
+  origspell=$out
+  spell "$dupdir"
+  dupspell=$out
+  $rdfind -makesymlinks true -symlinkstyle $style "$origspell" "$dupspell" >rdfind.out
+  verify [ -L "$dupdir/dup" ]
+  verify cmp "$origdir/orig" "$dupdir/dup"
+  link=$(readlink "$dupdir/dup")
+  if [ $style = absolute ]; then
+    expected=$(realpath "$origdir/orig")
+  else
+    expected=$(realpath --relative-to="$dupdir" "$origdir/orig")
+  fi
+  if [ "$link" != "$expected" ]; then
+    echo "linking \"$dupspell\" to \"$origspell\" gave \"$link\", not \"$expected\" with SEED=$seed"
+    exit 1
+  fi
+done
+
+dbgecho "all is good in this test!"
@@ -0,0 +1,49 @@
+#!/bin/sh
+# Ensures -progressive finds the same duplicates as checksumming whole files,
+# also for files which differ in the middle or just before the last bytes.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+makefiles() {
+  mkdir -p dir
+  head -c3000000 /dev/urandom >dir/a
+  cp dir/a dir/b
+  cp dir/a dir/c
+  patchbyte dir/c 1000000
+  cp dir/a dir/d
+  patchbyte dir/d 2999900
+  cp dir/a dir/e
+  patchbyte dir/e 70000
+  cp dir/e dir/f
+  head -c100 /dev/urandom >dir/small1
+  cp dir/small1 dir/small2
+}
+
+for checksumtype in $allchecksumtypes; do
+  reset_teststate
+  makefiles
+  # same number of arguments, so the priorities in the results files match
+  $rdfind -checksum "$checksumtype" -progressive false -threads 1 \
+    -outputname whole.txt dir >rdfind.out
+  for threads in 1 4; do
+    $rdfind -checksum "$checksumtype" -progressive true -threads $threads \
+      -outputname progressive.txt dir >rdfind.out
+    verify grep -q "^It seems like you have 6 files that are not unique$" rdfind.out
+    verify cmp whole.txt progressive.txt
+  done
+  dbgecho "passed $checksumtype"
+done
+
+reset_teststate
+makefiles
+$rdfind -progressive true -deleteduplicates true dir >rdfind.out
+for f in a c d e small1; do
+  verify [ -e dir/$f ]
+done
+for f in b f small2; do
+  verify [ ! -e dir/$f ]
+done
+dbgecho "passed delete test"
+
+dbgecho "all is good in this test!"
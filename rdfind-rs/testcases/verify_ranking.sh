@@ -0,0 +1,86 @@
+#!/bin/sh
+#
+# Ensures that ranking works as intended, ported from
+# orig_src/testcases/verify_ranking.sh. Instead of deleting duplicates, the
+# choice of original is read back from the results file.
+#
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+#create
+cr8() {
+  while [ $# -gt 0 ]; do
+    mkdir -p "$(dirname "$1")"
+    # make sure the file is longer than what fits in the byte buffer
+    head -c1000 /dev/zero >"$1"
+    shift
+  done
+}
+
+local_reset() {
+  reset_teststate
+  cr8 "$@"
+}
+
+# asserts that $1 is the original and the remaining arguments are its
+# duplicates, carrying the negated identity of the original.
+verify_original() {
+  orig=$1
+  shift
+  origid=$(awk -v n="$orig" '$1 == "DUPTYPE_FIRST_OCCURRENCE" && $8 == n { print $2 }' results.txt)
+  verify [ -n "$origid" ]
+  verify [ "$origid" -gt 0 ]
+  while [ $# -gt 0 ]; do
+    dupid=$(awk -v n="$1" '$1 != "DUPTYPE_FIRST_OCCURRENCE" && $8 == n { print $2 }' results.txt)
+    verify [ "$dupid" = "-$origid" ]
+    shift
+  done
+}
+
+#enforce the rules form RANKING in the man page.
+
+#Rule 1: If A was found while scanning an input argument earlier than B, A is higher ranked.
+
+local_reset a b
+$rdfind a b >rdfind.out
+verify_original a b
+
+local_reset a sd0/a
+$rdfind a sd0/a >rdfind.out
+verify_original a sd0/a
+verify grep -q "^DUPTYPE_OUTSIDE_TREE .* sd0/a$" results.txt
+
+local_reset a sd0/a
+$rdfind sd0/a a >rdfind.out
+verify_original sd0/a a
+
+local_reset a sd0/sd1/sd2/a
+$rdfind sd0/sd1/sd2/a a >rdfind.out
+verify_original sd0/sd1/sd2/a a
+
+dbgecho "tests for rule 1 passed ok"
+
+#Rule 2: If A was found at a depth lower than B, A is higher ranked (A closer to the root)
+local_reset sd0/a sd0/sd1/sd2/a
+$rdfind sd0 >rdfind.out
+verify_original sd0/a sd0/sd1/sd2/a
+verify grep -q "^DUPTYPE_WITHIN_SAME_TREE .* sd0/sd1/sd2/a$" results.txt
+
+local_reset sd0/a sd0/sd1/b0 sd0/sd1/b1 sd0/sd1/sd2/c
+$rdfind sd0 >rdfind.out
+verify_original sd0/a sd0/sd1/b0 sd0/sd1/b1 sd0/sd1/sd2/c
+
+dbgecho "tests for rule 2 passed ok"
+
+#Rule 3: If A was found earlier than B, A is higher ranked.
+#This needs disorderfs to control the order the filesystem lists files in,
+#see orig_src/testcases/verify_ranking.sh. With deterministic operation
+#(the default) the order is by name instead.
+local_reset sd0/b sd0/a
+$rdfind sd0 >rdfind.out
+verify_original sd0/a sd0/b
+
+dbgecho "tests for rule 3 passed ok"
+
+dbgecho "all is good for the ranking tests!"
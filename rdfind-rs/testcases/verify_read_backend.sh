@@ -0,0 +1,33 @@
+#!/bin/sh
+# Ensures all read backends find the same duplicates, for files of sizes
+# around the block sizes O_DIRECT reads in.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+reset_teststate
+mkdir dir
+for size in 1 63 64 65 4095 4096 4097 8193 3000000; do
+  head -c$size /dev/urandom >dir/a$size
+  cp dir/a$size dir/b$size
+  cp dir/a$size dir/c$size
+  patchbyte dir/c$size $((size / 2))
+done
+
+# same number of arguments, so the priorities in the results files match
+$rdfind -readbackend buffered -progressive false -outputname expected.txt dir >rdfind.out
+for backend in buffered mmap direct; do
+  for progressive in false true; do
+    $rdfind -readbackend $backend -progressive $progressive \
+      -outputname results.txt dir >rdfind.out
+    verify grep -q "^It seems like you have 18 files that are not unique$" rdfind.out
+    verify cmp expected.txt results.txt
+  done
+  for firstbytes in 100 5000; do
+    $rdfind -readbackend $backend -firstbytes $firstbytes -lastbytes $firstbytes dir >rdfind.out
+    verify grep -q "^It seems like you have 18 files that are not unique$" rdfind.out
+  done
+  dbgecho "passed $backend"
+done
+
+dbgecho "all is good in this test!"
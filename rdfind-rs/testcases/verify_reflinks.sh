@@ -0,0 +1,39 @@
+#!/bin/sh
+# Ensures -makereflinks replaces duplicates with clones that keep their own
+# inode, permissions and times, or leaves them alone with a clear message on
+# file systems without reflinks.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+reset_teststate
+mkdir dir
+head -c100000 /dev/urandom >dir/a
+cp dir/a dir/b
+chmod 640 dir/b
+touch -d "2001-02-03 04:05:06" dir/b
+
+$rdfind -makereflinks true -dryrun true dir >rdfind.out
+verify grep -q "^(DRYRUN MODE) reflink dir/b to dir/a$" rdfind.out
+verify grep -q "^(DRYRUN MODE) Making 1 reflinks.$" rdfind.out
+dbgecho "passed dry run"
+
+status=0
+$rdfind -makereflinks true dir >rdfind.out 2>rdfind.err || status=$?
+if [ "$status" -eq 0 ]; then
+  verify grep -q "^Making 1 reflinks.$" rdfind.out
+  verify [ "$(stat -c %i dir/a)" != "$(stat -c %i dir/b)" ]
+  dbgecho "passed making a reflink"
+else
+  verify [ "$status" -eq 2 ]
+  verify grep -q "^Making 0 reflinks.$" rdfind.out
+  verify grep -q "does not support reflinks" rdfind.err
+  dbgecho "passed refusing on a file system without reflinks"
+fi
+# either way, b keeps its contents, permissions and times
+verify cmp dir/a dir/b
+verify [ "$(stat -c %a dir/b)" = 640 ]
+verify [ "$(stat -c %Y dir/b)" = "$(date -d "2001-02-03 04:05:06" +%s)" ]
+verify [ "$(ls -A dir | wc -l)" -eq 2 ]
+
+dbgecho "all is good in this test!"
@@ -0,0 +1,57 @@
+#!/bin/sh
+# Ensures -firstbytes and -lastbytes decide how much of each file is
+# compared before the checksum, and that long digests are not cut short.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+reset_teststate
+mkdir dir
+head -c10000 /dev/urandom >dir/a
+cp dir/a dir/b
+patchbyte dir/b 1000
+cp dir/a dir/c
+patchbyte dir/c 9000
+
+$rdfind dir >rdfind.out
+verify grep -q "^Now eliminating candidates based on first bytes: removed 0 files" rdfind.out
+verify grep -q "^Now eliminating candidates based on last bytes: removed 0 files" rdfind.out
+$rdfind -firstbytes 4K -lastbytes 2K dir >rdfind.out
+verify grep -q "^Now eliminating candidates based on first bytes: removed 1 files" rdfind.out
+verify grep -q "^Now eliminating candidates based on last bytes: removed 2 files" rdfind.out
+dbgecho "passed sampling more bytes"
+
+$rdfind -firstbytes 0 -lastbytes 0 dir >rdfind.out
+verify [ "$(grep -c "bytes:" rdfind.out)" -eq 0 ]
+verify grep -q "^It seems like you have 0 files that are not unique$" rdfind.out
+dbgecho "passed skipping the samples"
+
+# files no longer than the samples are compared on the samples alone
+reset_teststate
+mkdir dir
+head -c3000 /dev/urandom >dir/a
+cp dir/a dir/b
+head -c3000 /dev/urandom >dir/c
+$rdfind -firstbytes 4K dir >rdfind.out
+verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+dbgecho "passed short files"
+
+# the largest samples allowed, which are only as long as the files
+$rdfind -firstbytes 128M -lastbytes 128M dir >rdfind.out
+verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+dbgecho "passed the largest samples"
+
+# a digest longer than 64 bytes
+reset_teststate
+mkdir dir
+head -c100000 /dev/urandom >dir/a
+cp dir/a dir/b
+cp dir/a dir/c
+patchbyte dir/c 50000
+for progressive in false true; do
+  $rdfind -checksum sha512+sha256 -progressive $progressive dir >rdfind.out
+  verify grep -q "^It seems like you have 2 files that are not unique$" rdfind.out
+done
+dbgecho "passed a digest of 96 bytes"
+
+dbgecho "all is good in this test!"
@@ -0,0 +1,39 @@
+#!/bin/sh
+# Ensures duplicates which already share their extents with the original,
+# like reflinked copies, are marked as such and not counted as space that
+# can be reduced. On file systems without reflinks, they are plain copies.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+reset_teststate
+mkdir dir
+head -c100000 /dev/urandom >dir/a
+cp dir/a dir/plain
+sync
+
+# a plain copy is stored twice, and counted
+$rdfind dir >rdfind.out
+verify grep -q "^DUPTYPE_WITHIN_SAME_TREE .* dir/plain$" results.txt
+verify grep -q "^Totally, 98 KiB can be reduced.$" rdfind.out
+verify [ "$(grep -c "already share their data" rdfind.out)" -eq 0 ]
+dbgecho "passed plain copy"
+
+rm dir/plain
+if ! cp --reflink=always dir/a dir/clone 2>/dev/null; then
+  dbgecho "no reflinks on this file system, skipping the rest"
+  exit 0
+fi
+sync
+
+$rdfind dir >rdfind.out
+verify grep -q "^DUPTYPE_SHARED_EXTENTS .* dir/clone$" results.txt
+verify grep -q "^Totally, 0 B can be reduced.$" rdfind.out
+verify grep -q "^1 of the duplicates already share their data" rdfind.out
+dbgecho "passed reflinked copy"
+
+# the report reads the mark back
+$rdfind report >report.out
+verify grep -q "^Totally, 0 B can be reduced.$" report.out
+
+dbgecho "all is good in this test!"
@@ -0,0 +1,45 @@
+#!/bin/sh
+# Ensures -symlinkstyle relative makes links which resolve to the original,
+# also through ".." and symlinked directories, and keep working when the
+# tree is moved. -symlinkstyle absolute, the default, makes absolute links.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+makefiles() {
+  mkdir -p tree/a/x tree/b/y/z
+  head -c1000 /dev/urandom >tree/a/x/orig
+  cp tree/a/x/orig tree/b/y/z/dup
+  cp tree/a/x/orig tree/a/x/same
+}
+
+reset_teststate
+makefiles
+$rdfind -makesymlinks true -symlinkstyle relative tree/a tree/b >rdfind.out
+verify [ "$(readlink tree/b/y/z/dup)" = ../../../a/x/orig ]
+verify [ "$(readlink tree/a/x/same)" = orig ]
+verify cmp tree/a/x/orig tree/b/y/z/dup
+verify cmp tree/a/x/orig tree/a/x/same
+mv tree moved
+verify cmp moved/a/x/orig moved/b/y/z/dup
+verify cmp moved/a/x/orig moved/a/x/same
+dbgecho "passed relative links"
+
+# the duplicate is found through a path with ".." and a symlinked directory,
+# neither of which may end up in the link
+reset_teststate
+makefiles
+ln -s tree/b/y/z zlink
+$rdfind -makesymlinks true --symlink-style=relative tree/a/../a zlink/ >rdfind.out
+verify [ "$(readlink tree/b/y/z/dup)" = ../../../a/x/orig ]
+verify cmp tree/a/x/orig tree/b/y/z/dup
+dbgecho "passed relative links through .. and a symlinked directory"
+
+reset_teststate
+makefiles
+$rdfind -makesymlinks true -symlinkstyle absolute tree/a tree/b >rdfind.out
+verify [ "$(readlink tree/b/y/z/dup)" = "$datadir/tree/a/x/orig" ]
+verify cmp tree/a/x/orig tree/b/y/z/dup
+dbgecho "passed absolute links"
+
+dbgecho "all is good in this test!"
@@ -0,0 +1,45 @@
+#!/bin/sh
+# Ensures reading files on several threads gives the same results file as
+# reading them on one, both on a disk and on tmpfs, whose anonymous device
+# is read concurrently.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+# makes groups of duplicates of different sizes in the directory $1, with
+# files of the same size which differ at the start, the end or the middle
+makefiles() {
+  for i in 1 2 3 4 5 6; do
+    mkdir -p "$1/d$i"
+    head -c$((i * 3000)) /dev/urandom >"$1/d$i/orig"
+    for j in 1 2 3; do
+      cp "$1/d$i/orig" "$1/d$i/dup$j"
+      cp "$1/d$i/orig" "$1/d$i/differs$j"
+    done
+    patchbyte "$1/d$i/differs1" 0
+    patchbyte "$1/d$i/differs2" $((i * 3000 - 1))
+    patchbyte "$1/d$i/differs3" $((i * 1500))
+  done
+}
+
+# compares the results of -threads 1 and -threads 4 on the directory $1
+compare_threads() {
+  $rdfind -threads 1 -outputname results1.txt "$1" >rdfind1.out
+  $rdfind -threads 4 -outputname results4.txt "$1" >rdfind4.out
+  verify cmp results1.txt results4.txt
+  verify [ "$(grep -c "^DUPTYPE_WITHIN_SAME_TREE" results4.txt)" -eq 18 ]
+}
+
+reset_teststate
+makefiles dir
+compare_threads dir
+dbgecho "passed threads on disk"
+
+if shmdir=$(mktemp -d -p /dev/shm rdfindtestcases.XXXXXXXX 2>/dev/null); then
+  makefiles "$shmdir"
+  compare_threads "$shmdir"
+  rm -rf "$shmdir"
+  dbgecho "passed threads on tmpfs"
+fi
+
+dbgecho "all is good in this test!"
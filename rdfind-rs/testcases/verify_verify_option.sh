@@ -0,0 +1,38 @@
+#!/bin/sh
+# Ensures -verify keeps files which only share a checksum with their
+# original, using the md5 collision files from the upstream test suite.
+
+set -e
+. "$(dirname "$0")/common_funcs.sh"
+
+collisions="$testscriptsdir/../../orig_src/testcases/md5collisions"
+
+reset_teststate
+mkdir md5coll
+cp "$collisions/"*.ps md5coll
+
+status=0
+$rdfind -checksum md5 -verify true -deleteduplicates true md5coll \
+  >rdfind.out 2>rdfind.err || status=$?
+verify [ "$status" -eq 2 ]
+verify grep -q "^Deleted 0 files.$" rdfind.out
+verify grep -q "differs from" rdfind.err
+verify [ "$(ls md5coll | wc -l)" -eq 2 ]
+dbgecho "passed refusing to delete a collision"
+
+status=0
+$rdfind -checksum md5 -verify true -makehardlinks true -dryrun true md5coll \
+  >rdfind.out 2>rdfind.err || status=$?
+verify [ "$status" -eq 2 ]
+verify grep -q "^(DRYRUN MODE) Making 0 links.$" rdfind.out
+dbgecho "passed verifying in a dry run"
+
+reset_teststate
+mkdir dir
+head -c100000 /dev/urandom >dir/a
+cp dir/a dir/b
+$rdfind -verify true -makehardlinks true dir >rdfind.out
+verify [ "$(stat -c %i dir/a)" = "$(stat -c %i dir/b)" ]
+dbgecho "passed linking identical files"
+
+dbgecho "all is good in this test!"
@@ -0,0 +1,143 @@
+// Tests of registering a DuplicateHasher of one's own with
+// ChecksumType::register, and of the ways registering can fail.
+// See LICENSE for further details.
+
+use rdfind_rs::checksum::{Checksum, ChecksumType, DuplicateHasher, RegisterError};
+use rdfind_rs::scanner::Scanner;
+use std::fs;
+
+// sums the bytes, which is enough to tell the test files apart
+#[derive(Clone)]
+struct ByteSum(u64);
+
+impl DuplicateHasher for ByteSum {
+    fn update(&mut self, data: &[u8]) {
+        self.0 = data
+            .iter()
+            .fold(self.0, |sum, &byte| sum.wrapping_add(byte.into()));
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+
+    fn clone_box(&self) -> Box<dyn DuplicateHasher> {
+        Box::new(self.clone())
+    }
+
+    fn digest_length(&self) -> usize {
+        8
+    }
+}
+
+// a hasher which gives nothing to compare
+struct Empty;
+
+impl DuplicateHasher for Empty {
+    fn update(&mut self, _data: &[u8]) {}
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn clone_box(&self) -> Box<dyn DuplicateHasher> {
+        Box::new(Empty)
+    }
+
+    fn digest_length(&self) -> usize {
+        0
+    }
+}
+
+#[test]
+fn registered_hasher_is_chosen_by_name() {
+    let bytesum = ChecksumType::register("bytesum", || Box::new(ByteSum(0))).unwrap();
+    assert_eq!(bytesum.name(), "bytesum");
+    assert_eq!(ChecksumType::from_name("bytesum"), Some(bytesum));
+    assert!(ChecksumType::registered().contains(&bytesum));
+
+    let mut chk = Checksum::new(bytesum);
+    assert_eq!(chk.get_digest_length(), 8);
+    chk.update(b"\x01\x02").unwrap();
+    chk.update(b"\x03").unwrap();
+    assert_eq!(chk.finalize_to_vec(), 6u64.to_be_bytes());
+
+    // it combines with the built in ones like they do with each other
+    let combined = ChecksumType::from_name("bytesum+sha1").unwrap();
+    assert_eq!(Checksum::new(combined).get_digest_length(), 8 + 20);
+}
+
+#[test]
+fn registered_hasher_finds_duplicates() {
+    let bytesum = ChecksumType::register("bytesum-scan", || Box::new(ByteSum(0))).unwrap();
+    let dir = std::env::temp_dir().join(format!("rdfind-registry-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // the middle byte differs, so only the checksum tells a from b
+    let contents = |middle: u8| [vec![b'a'; 100], vec![middle], vec![b'z'; 100]].concat();
+    fs::write(dir.join("a"), contents(1)).unwrap();
+    fs::write(dir.join("b"), contents(2)).unwrap();
+    fs::write(dir.join("c"), contents(1)).unwrap();
+
+    let duplicates = Scanner::new().root(&dir).checksum(bytesum).scan();
+    let groups: Vec<_> = duplicates.groups().collect();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].original.filename, dir.join("a"));
+    assert_eq!(groups[0].duplicates.len(), 1);
+    assert_eq!(groups[0].duplicates[0].filename, dir.join("c"));
+}
+
+#[test]
+fn invalid_name() {
+    for name in ["", "has space", "a+b", "sl/ash"] {
+        assert_eq!(
+            ChecksumType::register(name, || Box::new(ByteSum(0))),
+            Err(RegisterError::InvalidName {
+                name: name.to_string()
+            })
+        );
+    }
+    assert_eq!(
+        RegisterError::InvalidName {
+            name: "a+b".to_string()
+        }
+        .to_string(),
+        "invalid checksum name \"a+b\""
+    );
+}
+
+#[test]
+fn name_taken() {
+    let err = ChecksumType::register("sha1", || Box::new(ByteSum(0))).unwrap_err();
+    assert_eq!(
+        err,
+        RegisterError::NameTaken {
+            name: "sha1".to_string()
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "a checksum named \"sha1\" is already registered"
+    );
+
+    ChecksumType::register("bytesum-twice", || Box::new(ByteSum(0))).unwrap();
+    assert_eq!(
+        ChecksumType::register("bytesum-twice", || Box::new(ByteSum(0))),
+        Err(RegisterError::NameTaken {
+            name: "bytesum-twice".to_string()
+        })
+    );
+}
+
+#[test]
+fn empty_digest() {
+    let err = ChecksumType::register("empty", || Box::new(Empty)).unwrap_err();
+    assert_eq!(
+        err,
+        RegisterError::EmptyDigest {
+            name: "empty".to_string()
+        }
+    );
+    assert_eq!(err.to_string(), "checksum \"empty\" has an empty digest");
+    assert_eq!(ChecksumType::from_name("empty"), None);
+}
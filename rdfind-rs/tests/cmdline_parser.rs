@@ -0,0 +1,132 @@
+// Tests of cmdline_parser::Parser through Parser::from_args, one for each
+// ParseError and the message it gives.
+// See LICENSE for further details.
+
+use rdfind_rs::cmdline_parser::{ParseError, Parser};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+
+fn parser(args: &[&str]) -> Parser {
+    Parser::from_args(std::iter::once("rdfind").chain(args.iter().copied()))
+}
+
+#[test]
+fn parses_bool_and_string_options() {
+    let mut p = parser(&["-dryrun", "true", "-outputname", "out.txt", "dir"]);
+    assert_eq!(p.try_parse_string("-outputname"), Ok(false));
+    assert_eq!(p.try_parse_bool("-dryrun"), Ok(true));
+    assert!(p.get_parsed_bool());
+    assert_eq!(p.advance(), 3);
+    assert_eq!(p.try_parse_string("-outputname"), Ok(true));
+    assert!(p.parsed_string_is("out.txt"));
+    p.advance();
+    assert_eq!(p.current_arg_is("dir"), Ok(true));
+    p.advance();
+    assert!(!p.has_args_left());
+}
+
+#[test]
+fn parses_long_options() {
+    let mut p = parser(&["--dry-run=false", "--checksum=sha256", "--min-size", "1M"]);
+    assert_eq!(p.try_parse_flag("--dry-run"), Ok(true));
+    assert!(!p.get_parsed_bool());
+    p.advance();
+    assert_eq!(p.try_parse_value("--checksum"), Ok(true));
+    assert!(p.parsed_string_is("sha256"));
+    p.advance();
+    assert_eq!(p.try_parse_value("--min-size"), Ok(true));
+    assert!(p.parsed_string_is("1M"));
+    assert_eq!(p.get_current_index(), 4);
+}
+
+#[test]
+fn accepts_arguments_which_are_not_utf8() {
+    let name = OsStr::from_bytes(b"caf\xe9");
+    let mut p = Parser::from_args([
+        OsString::from("rdfind"),
+        OsString::from("-outputname"),
+        name.to_os_string(),
+    ]);
+    assert_eq!(p.try_parse_string("-outputname"), Ok(true));
+    assert_eq!(p.get_parsed_string(), name);
+}
+
+#[test]
+fn out_of_bounds() {
+    let mut p = parser(&["dir"]);
+    p.advance();
+    let expected = ParseError::OutOfBounds { index: 2, argc: 2 };
+    assert_eq!(p.try_parse_bool("-dryrun"), Err(expected.clone()));
+    assert_eq!(p.try_parse_string("-outputname"), Err(expected.clone()));
+    assert_eq!(p.try_parse_flag("--dry-run"), Err(expected.clone()));
+    assert_eq!(p.try_parse_value("--checksum"), Err(expected.clone()));
+    assert_eq!(p.get_current_arg(), Err(expected.clone()));
+    assert_eq!(p.current_arg_is("dir"), Err(expected.clone()));
+    assert_eq!(expected.to_string(), "out of bounds: index=2 argc=2");
+}
+
+#[test]
+fn missing_bool() {
+    let mut p = parser(&["-dryrun"]);
+    let err = p.try_parse_bool("-dryrun").unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::MissingBool {
+            arg: "-dryrun".to_string()
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "expected true or false after -dryrun, not end of argument list."
+    );
+}
+
+#[test]
+fn invalid_bool() {
+    let mut p = parser(&["-makehardlinks", "maybe"]);
+    let err = p.try_parse_bool("-makehardlinks").unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::InvalidBool {
+            arg: "-makehardlinks".to_string(),
+            value: OsString::from("maybe"),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "expected true or false after -makehardlinks, not \"maybe\""
+    );
+
+    let mut p = parser(&["--make-hardlinks=yes"]);
+    assert_eq!(
+        p.try_parse_flag("--make-hardlinks"),
+        Err(ParseError::InvalidBool {
+            arg: "--make-hardlinks".to_string(),
+            value: OsString::from("yes"),
+        })
+    );
+}
+
+#[test]
+fn missing_string() {
+    let mut p = parser(&["-outputname"]);
+    let err = p.try_parse_string("-outputname").unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::MissingString {
+            arg: "-outputname".to_string()
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "expected string after -outputname, not end of argument list."
+    );
+
+    let mut p = parser(&["--checksum"]);
+    assert_eq!(
+        p.try_parse_value("--checksum"),
+        Err(ParseError::MissingString {
+            arg: "--checksum".to_string()
+        })
+    );
+}
@@ -0,0 +1,187 @@
+// Property tests of path_normalize::normalize on randomly spelled paths.
+// The paths are the same on every run, unless another seed is given in the
+// environment variable SEED. The seed is printed when a check fails.
+// See LICENSE for further details.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rdfind_rs::path_normalize::normalize;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+// how many paths each test checks
+const PATHS: usize = 2000;
+
+const DEFAULT_SEED: u64 = 20250505;
+
+// the names paths are made of, two of them not valid UTF-8
+const NAMES: [&[u8]; 7] = [b".", b"..", b"a", b"bb", b"caf\xe9", b"\xff\xfe", b""];
+
+fn seed() -> u64 {
+    match std::env::var("SEED") {
+        Ok(seed) => seed.parse().expect("SEED should be a number"),
+        Err(_) => DEFAULT_SEED,
+    }
+}
+
+// a path of up to 8 names, which may start or end with slashes, and have
+// several slashes between the names
+fn random_path(rng: &mut StdRng) -> PathBuf {
+    let mut bytes = Vec::new();
+    if rng.random_bool(0.5) {
+        bytes.push(b'/');
+    }
+    for i in 0..rng.random_range(0..8) {
+        if i > 0 {
+            bytes.extend(std::iter::repeat_n(b'/', rng.random_range(1..4)));
+        }
+        bytes.extend_from_slice(NAMES[rng.random_range(0..NAMES.len())]);
+    }
+    bytes.extend(std::iter::repeat_n(b'/', rng.random_range(0..3)));
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+fn random_paths(seed: u64) -> Vec<PathBuf> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..PATHS).map(|_| random_path(&mut rng)).collect()
+}
+
+fn names(path: &Path) -> Vec<&OsStr> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn idempotent() {
+    let seed = seed();
+    for path in random_paths(seed) {
+        let once = normalize(&path);
+        assert_eq!(normalize(&once), once, "{:?} with SEED={}", path, seed);
+    }
+}
+
+#[test]
+fn leaves_no_dots_or_extra_slashes() {
+    let seed = seed();
+    for path in random_paths(seed) {
+        let normalized = normalize(&path);
+        let bytes = normalized.as_os_str().as_bytes();
+        let context = format!("{:?} gave {:?} with SEED={}", path, normalized, seed);
+        if bytes == b"." || bytes == b"/" {
+            continue;
+        }
+        assert!(!bytes.is_empty(), "{}", context);
+        assert!(!bytes.ends_with(b"/"), "{}", context);
+        assert!(!bytes.windows(2).any(|w| w == b"//"), "{}", context);
+        assert!(
+            bytes.split(|&b| b == b'/').all(|name| name != b"."),
+            "{}",
+            context
+        );
+        // ".." is only left at the start of a relative path
+        let names = bytes.split(|&b| b == b'/');
+        let parents = names.clone().take_while(|&name| name == b"..").count();
+        assert!(names.skip(parents).all(|name| name != b".."), "{}", context);
+        if bytes.starts_with(b"/") {
+            assert_eq!(parents, 0, "{}", context);
+        }
+    }
+}
+
+#[test]
+fn keeps_names_which_are_not_utf8() {
+    let seed = seed();
+    for path in random_paths(seed) {
+        let normalized = normalize(&path);
+        let context = format!("{:?} gave {:?} with SEED={}", path, normalized, seed);
+        if path.components().any(|c| c == Component::ParentDir) {
+            // the names left are some of the ones given, in the same order
+            let mut given = names(&path).into_iter();
+            for name in names(&normalized) {
+                assert!(given.any(|g| g == name), "{}", context);
+            }
+        } else {
+            assert_eq!(names(&normalized), names(&path), "{}", context);
+        }
+    }
+}
+
+// paths and what "realpath -s -m" gives for them
+const RESOLVED: [(&[u8], &[u8]); 9] = [
+    (b"/a//./b/../c/.", b"/a/c"),
+    (b"/../a", b"/a"),
+    (b"/a/b/../../..", b"/"),
+    (b"//a//b//", b"/a/b"),
+    (b"/a/./b/./", b"/a/b"),
+    (b"/x/y/../../z/..", b"/"),
+    (b"/.", b"/"),
+    (b"/a/b/c/../../d", b"/a/d"),
+    (b"/caf\xe9/../\xff\xfe/./x/", b"/\xff\xfe/x"),
+];
+
+#[test]
+fn agrees_with_realpath_table() {
+    for (path, expected) in RESOLVED {
+        let path = Path::new(OsStr::from_bytes(path));
+        assert_eq!(
+            normalize(path).as_os_str().as_bytes(),
+            expected,
+            "{:?}",
+            path
+        );
+    }
+}
+
+// runs realpath from GNU coreutils on the random paths, where it is found
+#[test]
+fn agrees_with_realpath() {
+    let gnu = Command::new("realpath")
+        .args(["-s", "-m", "-z", "--", "/"])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !gnu {
+        eprintln!("realpath -s -m -z is not available, skipping");
+        return;
+    }
+    let seed = seed();
+    // relative paths are taken from a directory which does not exist, so
+    // no symlinks are in the way
+    let base = Path::new(OsStr::from_bytes(b"/nonexistent/d\xe9ep/dir"));
+    let paths: Vec<PathBuf> = random_paths(seed)
+        .into_iter()
+        .map(|path| base.join(path))
+        .collect();
+    let output = Command::new("realpath")
+        .args(["-s", "-m", "-z", "--"])
+        .args(&paths)
+        .output()
+        .expect("could not run realpath");
+    assert!(output.status.success(), "realpath failed");
+    let resolved: Vec<&[u8]> = output.stdout.split(|&b| b == 0).collect();
+    assert_eq!(resolved.len(), paths.len() + 1);
+    for (path, expected) in paths.iter().zip(resolved) {
+        assert_eq!(
+            normalize(path).as_os_str().as_bytes(),
+            expected,
+            "{:?} with SEED={}",
+            path,
+            seed
+        );
+    }
+    // a relative path may be normalised before it is joined to another
+    for path in random_paths(seed) {
+        assert_eq!(
+            normalize(&base.join(normalize(&path))),
+            normalize(&base.join(&path)),
+            "{:?} with SEED={}",
+            path,
+            seed
+        );
+    }
+}
@@ -0,0 +1,52 @@
+// Tests of the order Scanner compares candidates in.
+// See LICENSE for further details.
+
+use rdfind_rs::checksum::ChecksumType;
+use rdfind_rs::scanner::{Progress, Scanner};
+use std::fs;
+
+// the descriptions of the steps scanning with checksums takes, after the
+// first and last bytes
+fn checksum_steps(name: &str, checksums: &[ChecksumType]) -> Vec<String> {
+    let dir = std::env::temp_dir().join(format!("rdfind-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a"), [b'x'; 200]).unwrap();
+    fs::write(dir.join("b"), [b'x'; 200]).unwrap();
+    let mut scanner = Scanner::new().root(&dir).first_bytes(0).last_bytes(0);
+    for &checksumtype in checksums {
+        scanner = scanner.checksum(checksumtype);
+    }
+    let mut steps = Vec::new();
+    let duplicates = scanner.scan_with_progress(|progress| {
+        if let Progress::Eliminating { description } = progress {
+            steps.push(description.to_string());
+        }
+    });
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(duplicates.groups().count(), 1);
+    steps
+}
+
+#[test]
+fn crc32c_goes_first() {
+    assert_eq!(
+        checksum_steps("crc32c-first", &[ChecksumType::MD5, ChecksumType::CRC32C]),
+        ["crc32c checksum", "md5 checksum"]
+    );
+}
+
+#[test]
+fn crc32c_alone_is_followed_by_sha1() {
+    assert_eq!(
+        checksum_steps("crc32c-alone", &[ChecksumType::CRC32C]),
+        ["crc32c checksum", "sha1 checksum"]
+    );
+}
+
+#[test]
+fn others_keep_their_order() {
+    assert_eq!(
+        checksum_steps("in-order", &[ChecksumType::SHA256, ChecksumType::MD5]),
+        ["sha256 checksum", "md5 checksum"]
+    );
+}